@@ -2,10 +2,13 @@
 
 use std::path::PathBuf;
 
+#[path = "build/symbols.rs"]
+mod symbols;
+
 fn main() {
     // set by cargo, build scripts should use this directory for output files
     println!("std::env::var_os('OUT_DIR') = {:?}", std::env::var_os("OUT_DIR").unwrap());
-    
+
     /* I was just checking the environment variables below
     for (key, value) in std::env::vars_os() {
         println!("{key:?}: {value:?}");
@@ -16,16 +19,27 @@ fn main() {
     // https://doc.rust-lang.org/nightly/cargo/reference/unstable.html#artifact-dependencies
 
     let kernel = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_KERNEL_WITH_BOOTLOADER").unwrap());
-    
+
+    // write the kernel's symbol table to a file that is passed to the kernel as its ramdisk,
+    // so the kernel can print function names in backtraces (see backtrace.rs in the kernel)
+    let symbols_path = out_dir.join("kernel.syms");
+    symbols::write_symbol_table(&kernel, &symbols_path);
+
     // create an UEFI disk image (optional)
     let uefi_path = out_dir.join("uefi.img");
-    bootloader::UefiBoot::new(&kernel).create_disk_image(&uefi_path).unwrap();
+    bootloader::UefiBoot::new(&kernel)
+        .set_ramdisk(&symbols_path)
+        .create_disk_image(&uefi_path)
+        .unwrap();
 
     // create a BIOS disk image
     let bios_path = out_dir.join("bios.img");
-    bootloader::BiosBoot::new(&kernel).create_disk_image(&bios_path).unwrap();
+    bootloader::BiosBoot::new(&kernel)
+        .set_ramdisk(&symbols_path)
+        .create_disk_image(&bios_path)
+        .unwrap();
 
     // pass the disk image paths as env variables to the main.rs
     println!("cargo:rustc-env=UEFI_PATH={}", uefi_path.display());
     println!("cargo:rustc-env=BIOS_PATH={}", bios_path.display());
-}
\ No newline at end of file
+}
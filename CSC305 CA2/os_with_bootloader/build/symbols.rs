@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use object::{Object, ObjectSymbol, SymbolKind};
+
+/* The symbol table is a text file with one function per line, sorted by address:
+    <start address in hex> <size in hex> <demangled name>
+The addresses are the ones in the ELF file, so the kernel adds its load offset (kernel_image_offset).
+The object crate reads the ELF .symtab and rustc-demangle turns the mangled names into readable paths.
+Ref: https://docs.rs/object/latest/object/read/index.html */
+pub fn write_symbol_table(kernel: &Path, out_path: &Path) {
+    let elf = std::fs::read(kernel).unwrap();
+    let mut symbols = function_symbols(&elf);
+    symbols.sort();
+    symbols.dedup_by_key(|(start, _, _)| *start); //aliases of the same function
+
+    let mut table = String::new();
+    for (start, size, name) in symbols {
+        //{:#} leaves out the hash, e.g. kernel_with_bootloader::interrupts::init instead of ...::init::h0123..
+        //Anything that isn't a Rust name (e.g. memcpy) is kept as it is
+        table.push_str(&format!("{:x} {:x} {:#}\n", start, size, rustc_demangle::demangle(&name)));
+    }
+    std::fs::write(out_path, table).unwrap();
+}
+
+// (start, size, mangled name) of every function in the kernel's symbol table
+fn function_symbols(elf: &[u8]) -> Vec<(u64, u64, String)> {
+    let file = object::File::parse(elf).expect("kernel is not an ELF file");
+    let symbols: Vec<_> = file
+        .symbols()
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+        .filter_map(|symbol| Some((symbol.address(), symbol.size(), symbol.name().ok()?.to_string())))
+        .collect();
+    if symbols.is_empty() {
+        println!("cargo:warning=the kernel has no symbol table, backtraces will show addresses only");
+    }
+    symbols
+}
@@ -0,0 +1,276 @@
+pub mod bench;
+pub mod stats;
+
+//The heap allocator backends. Exactly one is compiled in, chosen by cargo feature (see Cargo.toml).
+//good_memory_allocator is used when none of the features is enabled.
+#[cfg(feature = "buddy-allocator")]
+mod buddy;
+#[cfg(feature = "bump-allocator")]
+mod bump;
+#[cfg(feature = "fixed-size-block-allocator")]
+mod fixed_size_block;
+#[cfg(not(any(
+    feature = "bump-allocator",
+    feature = "linked-list-allocator",
+    feature = "fixed-size-block-allocator",
+    feature = "buddy-allocator"
+)))]
+mod good_memory;
+#[cfg(feature = "linked-list-allocator")]
+mod linked_list;
+
+#[cfg(any(
+    all(feature = "bump-allocator", feature = "linked-list-allocator"),
+    all(feature = "bump-allocator", feature = "fixed-size-block-allocator"),
+    all(feature = "bump-allocator", feature = "buddy-allocator"),
+    all(feature = "linked-list-allocator", feature = "fixed-size-block-allocator"),
+    all(feature = "linked-list-allocator", feature = "buddy-allocator"),
+    all(feature = "fixed-size-block-allocator", feature = "buddy-allocator"),
+))]
+compile_error!("enable at most one heap allocator backend feature");
+
+#[cfg(feature = "buddy-allocator")]
+pub use buddy::{new_backend, Backend};
+#[cfg(feature = "bump-allocator")]
+pub use bump::{new_backend, Backend};
+#[cfg(feature = "fixed-size-block-allocator")]
+pub use fixed_size_block::{new_backend, Backend};
+#[cfg(not(any(
+    feature = "bump-allocator",
+    feature = "linked-list-allocator",
+    feature = "fixed-size-block-allocator",
+    feature = "buddy-allocator"
+)))]
+pub use good_memory::{new_backend, Backend};
+#[cfg(feature = "linked-list-allocator")]
+pub use linked_list::{new_backend, Backend};
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+use crate::memory::{self, paging::MapError};
+use stats::{HeapMark, HeapStats, TrackingAllocator};
+
+/// Start of the virtual range reserved for the kernel heap. Far away from what the bootloader maps.
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+
+/// Size of the virtual range reserved for the heap. Only the part in use is actually mapped.
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024; // 64 MiB
+
+/// How much of the heap range is mapped at boot.
+pub const HEAP_INITIAL_SIZE: usize = 64 * 1024; // 64 KiB
+
+/// Each time the heap grows it maps at least this much (or as much as is already mapped, whichever is larger).
+const HEAP_MIN_GROWTH: usize = 64 * 1024;
+
+const PAGE_SIZE: usize = 4096;
+
+const HEAP_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// What [GrowableHeap] needs from a heap allocator backend.
+///
+/// A backend only manages the memory it has been given. Whenever it returns null,
+/// [GrowableHeap] maps more of the heap range and hands it over with [HeapBackend::add_region].
+pub trait HeapBackend: GlobalAlloc + Sync {
+    /// Shown by the benchmark and in the out-of-memory report.
+    const NAME: &'static str;
+
+    /// Gives `size` more bytes starting at `start` to the backend. The heap only grows upwards,
+    /// so `start` is always the end of the previously added region.
+    ///
+    /// # Safety
+    /// The memory must be mapped, unused, and stay valid forever.
+    unsafe fn add_region(&self, start: usize, size: usize);
+
+    /// The biggest block alloc() could hand out right now without a new region, for the benchmark.
+    /// None (the default) lets the benchmark find out by trying allocations; a backend that doesn't get memory
+    /// back from dealloc (bump) has to answer itself.
+    fn largest_free(&self) -> Option<usize> {
+        None
+    }
+}
+
+//The heap is wrapped in a TrackingAllocator so that demos can see how much memory they use. See heap_stats()
+#[global_allocator]
+pub static ALLOCATOR: TrackingAllocator<GrowableHeap<Backend>> =
+    TrackingAllocator::new(GrowableHeap::new(new_backend()));
+
+/// The kernel's global allocator: a fixed virtual range starting at [HEAP_START], mapped on demand
+/// and handed to the backend `B` piece by piece.
+pub struct GrowableHeap<B> {
+    backend: B,
+    heap_end: AtomicUsize, //end of the mapped part of the heap range
+    grow_lock: Mutex<()>,  //only one CPU maps new heap pages at a time
+}
+
+impl<B: HeapBackend> GrowableHeap<B> {
+    const fn new(backend: B) -> Self {
+        GrowableHeap {
+            backend,
+            heap_end: AtomicUsize::new(HEAP_START),
+            grow_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Bytes of the heap range that are currently mapped.
+    pub fn mapped_size(&self) -> usize {
+        self.heap_end.load(Ordering::Acquire) - HEAP_START
+    }
+
+    /// Maps more of the heap range, enough for at least `layout`, and hands it to the backend.
+    /// Returns false if the heap range is exhausted or there are no physical frames left.
+    fn grow(&self, layout: Layout) -> bool {
+        let start = self.heap_end.load(Ordering::Acquire);
+        let available = HEAP_START + HEAP_MAX_SIZE - start;
+
+        //leave room for the backend's bookkeeping and for aligning the block
+        let needed = align_up(layout.size() + layout.align() + 64, PAGE_SIZE);
+        let size = align_up(needed.max(self.mapped_size()).max(HEAP_MIN_GROWTH), PAGE_SIZE).min(available);
+        if size < needed {
+            return false;
+        }
+
+        if memory::map_range(VirtAddr::new(start as u64), size as u64, HEAP_FLAGS).is_err() {
+            return false;
+        }
+        self.add_region(start, size);
+        true
+    }
+
+    fn add_region(&self, start: usize, size: usize) {
+        unsafe {
+            self.backend.add_region(start, size);
+        }
+        self.heap_end.store(start + size, Ordering::Release);
+    }
+}
+
+//Every allocation runs with interrupts disabled. The backends use spinlocks, and a thread preempted while holding
+//one would leave every other thread's allocation spinning; worse, code that allocates with interrupts disabled
+//(like the scheduler) would spin forever. With interrupts off, the lock is never held across a context switch.
+unsafe impl<B: HeapBackend> GlobalAlloc for GrowableHeap<B> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        interrupts::without_interrupts(|| self.alloc_inner(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        interrupts::without_interrupts(|| self.backend.dealloc(ptr, layout))
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        interrupts::without_interrupts(|| self.realloc_inner(ptr, layout, new_size))
+    }
+}
+
+impl<B: HeapBackend> GrowableHeap<B> {
+    unsafe fn alloc_inner(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.backend.alloc(layout);
+        if !ptr.is_null() {
+            return ptr;
+        }
+
+        //out of room: map more of the heap range and try again
+        let _guard = self.grow_lock.lock();
+        //somebody else may have grown the heap while we waited for the lock
+        let mut ptr = self.backend.alloc(layout);
+        //some backends cannot use all of a new region for one block (alignment, bookkeeping),
+        //so keep growing until the block fits or the heap is exhausted
+        while ptr.is_null() && self.grow(layout) {
+            ptr = self.backend.alloc(layout);
+        }
+        ptr
+    }
+
+    unsafe fn realloc_inner(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.backend.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            return new_ptr;
+        }
+
+        //the backend is full; move the block to new memory (growing the heap if needed)
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc_inner(new_layout);
+        if !new_ptr.is_null() {
+            core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.backend.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+/// Maps the first [HEAP_INITIAL_SIZE] bytes of the heap range and hands them to the allocator.
+/// Needs the memory module (frame allocator and mapper) to be initialized.
+pub fn init() -> Result<(), MapError> {
+    let reserved_is_free = memory::with_mapper(|mapper| {
+        mapper.is_range_unmapped(VirtAddr::new(HEAP_START as u64), HEAP_MAX_SIZE as u64)
+    });
+    assert!(reserved_is_free, "the kernel heap range {:#x}.. is already in use", HEAP_START);
+
+    memory::map_range(VirtAddr::new(HEAP_START as u64), HEAP_INITIAL_SIZE as u64, HEAP_FLAGS)?;
+    ALLOCATOR.inner().add_region(HEAP_START, HEAP_INITIAL_SIZE);
+    Ok(())
+}
+
+/// Current allocation counters of the kernel heap.
+pub fn heap_stats() -> HeapStats {
+    ALLOCATOR.stats()
+}
+
+/// Remembers the current heap state (and restarts peak tracking) so that a demo or test
+/// can later check with [HeapMark::compare] that everything it allocated was freed again.
+pub fn heap_mark() -> HeapMark {
+    ALLOCATOR.reset_peak();
+    HeapMark::new(heap_stats())
+}
+
+/// A summary of the heap that can be printed without allocating.
+pub struct HeapReport;
+
+impl fmt::Display for HeapReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            " heap range: {:#x}..{:#x} ({} KiB reserved), backend: {}",
+            HEAP_START,
+            HEAP_START + HEAP_MAX_SIZE,
+            HEAP_MAX_SIZE / 1024,
+            Backend::NAME
+        )?;
+        writeln!(f, " heap mapped: {} KiB", ALLOCATOR.inner().mapped_size() / 1024)?;
+        writeln!(f, "{}", heap_stats())?;
+        //try_lock: we may have run out of memory while the frame allocator was locked
+        match memory::FRAME_ALLOCATOR.try_lock() {
+            Some(guard) => match guard.as_ref() {
+                Some(frames) => write!(f, " free physical frames: {} of {}", frames.free_frames(), frames.usable_frames()),
+                None => write!(f, " free physical frames: frame allocator not initialized"),
+            },
+            None => write!(f, " free physical frames: unknown (frame allocator busy)"),
+        }
+    }
+}
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    panic!(
+        "OUT OF MEMORY: allocation of {} bytes (align {}) failed\n{}",
+        layout.size(),
+        layout.align(),
+        HeapReport
+    )
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
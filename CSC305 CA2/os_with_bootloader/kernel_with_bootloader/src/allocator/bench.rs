@@ -0,0 +1,139 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::arch::x86_64::_rdtsc;
+
+use alloc::format;
+
+use crate::smart_pointer_examples::{add_child, create_tree};
+use crate::std::prelude::*;
+
+use super::{Backend, HeapBackend, ALLOCATOR};
+
+const BOXES: usize = 2000;
+const VECTORS: usize = 500;
+const STRINGS: usize = 500;
+const TREES: usize = 100;
+const CHILDREN_PER_TREE: usize = 8;
+
+/// Runs the same allocation pattern on whichever backend was selected with cargo features
+/// and prints how long it took and how fragmented it left the heap.
+///
+/// The pattern: many small Box/Vec/String allocations plus the Rc tree demo, then every
+/// other allocation is freed to leave holes, then everything is freed.
+pub fn run() {
+    println!("\nHeap benchmark for the {} allocator", Backend::NAME);
+    let mark = super::heap_mark();
+    let start = unsafe { _rdtsc() };
+
+    let mut boxes: Vec<Box<u64>> = Vec::new();
+    for i in 0..BOXES {
+        boxes.push(Box::new(i as u64));
+    }
+
+    let mut vectors: Vec<Vec<u32>> = Vec::new();
+    for i in 0..VECTORS {
+        let mut vector = Vec::new();
+        for j in 0..(i % 64) as u32 {
+            vector.push(j); //grows by reallocating
+        }
+        vectors.push(vector);
+    }
+
+    let mut strings: Vec<String> = Vec::new();
+    for i in 0..STRINGS {
+        strings.push(format!("string number {}", i));
+    }
+
+    let mut trees = Vec::new();
+    for _ in 0..TREES {
+        let root = create_tree();
+        for _ in 0..CHILDREN_PER_TREE {
+            add_child(&root);
+        }
+        trees.push(root);
+    }
+    let allocated = unsafe { _rdtsc() };
+
+    //free every other allocation, leaving holes between the survivors
+    keep_every_other(&mut boxes);
+    keep_every_other(&mut vectors);
+    keep_every_other(&mut strings);
+    keep_every_other(&mut trees);
+    let half_freed = unsafe { _rdtsc() };
+
+    let fragmentation = Fragmentation::measure();
+
+    drop(boxes);
+    drop(vectors);
+    drop(strings);
+    drop(trees);
+    let end = unsafe { _rdtsc() };
+
+    println!(
+        " cycles: {} allocating, {} freeing half, {} freeing the rest, {} total",
+        allocated - start,
+        half_freed - allocated,
+        end - half_freed,
+        end - start
+    );
+    println!(" peak heap use: {} bytes", mark.compare().peak_bytes_in_use);
+    println!("{}", fragmentation);
+    println!(" heap after benchmark: {}", mark.compare());
+}
+
+fn keep_every_other<T>(items: &mut Vec<T>) {
+    let mut index = 0;
+    items.retain(|_| {
+        index += 1;
+        index % 2 == 0
+    });
+}
+
+/// How badly the free memory of the heap is split up.
+struct Fragmentation {
+    mapped: usize,
+    in_use: usize,
+    largest_free_block: usize,
+}
+
+impl Fragmentation {
+    fn measure() -> Self {
+        let mapped = ALLOCATOR.inner().mapped_size();
+        let in_use = ALLOCATOR.stats().bytes_in_use;
+        let free = mapped.saturating_sub(in_use);
+        let largest_free_block = ALLOCATOR.inner().backend().largest_free().unwrap_or_else(|| largest_free_block(free));
+        Fragmentation { mapped, in_use, largest_free_block }
+    }
+}
+
+impl core::fmt::Display for Fragmentation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let free = self.mapped.saturating_sub(self.in_use);
+        //external fragmentation: the share of free memory that is not in the largest free block
+        let percent = (self.largest_free_block * 100).checked_div(free).map_or(0, |kept| 100 - kept);
+        write!(
+            f,
+            " heap mapped: {} bytes, in use: {}, largest free block: {}, fragmentation: {}%",
+            self.mapped, self.in_use, self.largest_free_block, percent
+        )
+    }
+}
+
+//Binary search for the biggest block the backend can hand out without growing the heap, for backends that
+//don't know it themselves (HeapBackend::largest_free).
+//Goes straight to the backend, so neither the heap statistics nor the heap size change.
+fn largest_free_block(upper_bound: usize) -> usize {
+    let backend = ALLOCATOR.inner().backend();
+    let (mut low, mut high) = (0, upper_bound);
+    while low < high {
+        let size = (low + high).div_ceil(2);
+        let layout = Layout::from_size_align(size, 8).unwrap();
+        let ptr = unsafe { backend.alloc(layout) };
+        if ptr.is_null() {
+            high = size - 1;
+        } else {
+            unsafe { backend.dealloc(ptr, layout) };
+            low = size;
+        }
+    }
+    low
+}
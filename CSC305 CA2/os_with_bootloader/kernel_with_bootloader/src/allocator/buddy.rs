@@ -0,0 +1,21 @@
+use buddy_system_allocator::LockedHeap;
+
+use super::HeapBackend;
+
+/// Blocks of up to 2^(ORDER - 1) bytes; 32 is far more than the heap range needs.
+const ORDER: usize = 32;
+
+pub type Backend = LockedHeap<ORDER>;
+
+pub const fn new_backend() -> Backend {
+    LockedHeap::empty()
+}
+
+//buddy_system_allocator splits power-of-two blocks in halves and merges freed "buddies" back
+impl HeapBackend for LockedHeap<ORDER> {
+    const NAME: &'static str = "buddy_system_allocator";
+
+    unsafe fn add_region(&self, start: usize, size: usize) {
+        self.lock().add_to_heap(start, start + size);
+    }
+}
@@ -0,0 +1,80 @@
+use core::alloc::{GlobalAlloc, Layout};
+
+use spin::Mutex;
+
+use super::HeapBackend;
+
+pub type Backend = BumpAllocator;
+
+pub const fn new_backend() -> Backend {
+    BumpAllocator {
+        inner: Mutex::new(Bump {
+            heap_start: 0,
+            heap_end: 0,
+            next: 0,
+            allocations: 0,
+        }),
+    }
+}
+
+/// Hands out memory by moving `next` forward. Nothing is reused until every allocation
+/// has been freed, at which point the whole heap is reset.
+/// Ref: https://os.phil-opp.com/allocator-designs/#bump-allocator
+pub struct BumpAllocator {
+    inner: Mutex<Bump>,
+}
+
+struct Bump {
+    heap_start: usize,
+    heap_end: usize,
+    next: usize,
+    allocations: usize,
+}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut bump = self.inner.lock();
+
+        let alloc_start = super::align_up(bump.next, layout.align());
+        let alloc_end = match alloc_start.checked_add(layout.size()) {
+            Some(end) => end,
+            None => return core::ptr::null_mut(),
+        };
+
+        if alloc_end > bump.heap_end {
+            core::ptr::null_mut() //out of memory
+        } else {
+            bump.next = alloc_end;
+            bump.allocations += 1;
+            alloc_start as *mut u8
+        }
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+        let mut bump = self.inner.lock();
+
+        bump.allocations -= 1;
+        if bump.allocations == 0 {
+            bump.next = bump.heap_start;
+        }
+    }
+}
+
+impl HeapBackend for BumpAllocator {
+    const NAME: &'static str = "bump";
+
+    unsafe fn add_region(&self, start: usize, size: usize) {
+        let mut bump = self.inner.lock();
+        if bump.heap_start == 0 {
+            bump.heap_start = start;
+            bump.next = start;
+        }
+        bump.heap_end = start + size; //regions are contiguous, so the heap just gets longer
+    }
+
+    //only what is after `next`: freed blocks before it come back when everything is freed
+    fn largest_free(&self) -> Option<usize> {
+        let bump = self.inner.lock();
+        Some(bump.heap_end - bump.next)
+    }
+}
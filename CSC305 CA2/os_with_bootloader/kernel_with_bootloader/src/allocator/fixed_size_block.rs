@@ -0,0 +1,109 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+use spin::Mutex;
+
+use super::HeapBackend;
+
+/// The block sizes to use. Each size is also the block's alignment, so they must be powers of two.
+/// Allocations larger than the biggest block size go to the fallback allocator.
+const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+pub type Backend = FixedSizeBlockAllocator;
+
+pub const fn new_backend() -> Backend {
+    FixedSizeBlockAllocator {
+        inner: Mutex::new(FixedSizeBlocks {
+            list_heads: [const { None }; BLOCK_SIZES.len()],
+            fallback: linked_list_allocator::Heap::empty(),
+        }),
+    }
+}
+
+/// Keeps one free list per block size (a slab allocator of sorts). Freed blocks go back
+/// to their list, so small allocations are O(1). New blocks and big allocations come from
+/// a linked list allocator.
+/// Ref: https://os.phil-opp.com/allocator-designs/#fixed-size-block-allocator
+pub struct FixedSizeBlockAllocator {
+    inner: Mutex<FixedSizeBlocks>,
+}
+
+struct ListNode {
+    next: Option<&'static mut ListNode>,
+}
+
+struct FixedSizeBlocks {
+    list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
+    fallback: linked_list_allocator::Heap,
+}
+
+impl FixedSizeBlocks {
+    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+        match self.fallback.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+}
+
+/// Index of the smallest block size that fits `layout`, or None if it needs the fallback.
+fn list_index(layout: &Layout) -> Option<usize> {
+    let required_block_size = layout.size().max(layout.align());
+    BLOCK_SIZES.iter().position(|&size| size >= required_block_size)
+}
+
+unsafe impl GlobalAlloc for FixedSizeBlockAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.inner.lock();
+        match list_index(&layout) {
+            Some(index) => match allocator.list_heads[index].take() {
+                Some(node) => {
+                    allocator.list_heads[index] = node.next.take();
+                    node as *mut ListNode as *mut u8
+                }
+                None => {
+                    //no block of that size free: carve a new one out of the fallback allocator
+                    let block_size = BLOCK_SIZES[index];
+                    let block_align = block_size;
+                    let layout = Layout::from_size_align(block_size, block_align).unwrap();
+                    allocator.fallback_alloc(layout)
+                }
+            },
+            None => allocator.fallback_alloc(layout),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut allocator = self.inner.lock();
+        match list_index(&layout) {
+            Some(index) => {
+                let new_node = ListNode {
+                    next: allocator.list_heads[index].take(),
+                };
+                //every block is big enough and aligned for a ListNode
+                assert!(core::mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+                assert!(core::mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+                let new_node_ptr = ptr as *mut ListNode;
+                new_node_ptr.write(new_node);
+                allocator.list_heads[index] = Some(&mut *new_node_ptr);
+            }
+            None => {
+                let ptr = NonNull::new(ptr).unwrap();
+                allocator.fallback.deallocate(ptr, layout);
+            }
+        }
+    }
+}
+
+impl HeapBackend for FixedSizeBlockAllocator {
+    const NAME: &'static str = "fixed-size block";
+
+    unsafe fn add_region(&self, start: usize, size: usize) {
+        let mut allocator = self.inner.lock();
+        if allocator.fallback.size() == 0 {
+            allocator.fallback.init(start as *mut u8, size);
+        } else {
+            allocator.fallback.extend(size); //regions are contiguous, so the fallback heap just gets longer
+        }
+    }
+}
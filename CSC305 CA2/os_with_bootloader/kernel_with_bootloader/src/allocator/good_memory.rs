@@ -0,0 +1,77 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use good_memory_allocator::SpinLockedAllocator;
+
+use super::HeapBackend;
+
+/// good_memory_allocator manages a single region given to it once at init, so every region
+/// the heap grows by becomes a new arena with an allocator of its own.
+const MAX_ARENAS: usize = 16;
+
+pub type Backend = GoodMemoryArenas;
+
+pub const fn new_backend() -> Backend {
+    GoodMemoryArenas {
+        arenas: [const { SpinLockedAllocator::empty() }; MAX_ARENAS],
+        arena_starts: [const { AtomicUsize::new(0) }; MAX_ARENAS + 1],
+        arena_count: AtomicUsize::new(0),
+    }
+}
+
+/// Arena `i` covers `arena_starts[i]..arena_starts[i + 1]`. Arenas are only ever added,
+/// so the ranges can be read without a lock once `arena_count` says they exist.
+pub struct GoodMemoryArenas {
+    arenas: [SpinLockedAllocator; MAX_ARENAS],
+    arena_starts: [AtomicUsize; MAX_ARENAS + 1],
+    arena_count: AtomicUsize,
+}
+
+impl GoodMemoryArenas {
+    fn arena_of(&self, ptr: *mut u8) -> usize {
+        let addr = ptr as usize;
+        let count = self.arena_count.load(Ordering::Acquire);
+        (0..count)
+            .find(|&i| {
+                self.arena_starts[i].load(Ordering::Relaxed) <= addr
+                    && addr < self.arena_starts[i + 1].load(Ordering::Relaxed)
+            })
+            .expect("pointer outside the kernel heap")
+    }
+}
+
+unsafe impl GlobalAlloc for GoodMemoryArenas {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        //newest arena first, as it is the one most likely to have room
+        let count = self.arena_count.load(Ordering::Acquire);
+        for i in (0..count).rev() {
+            let ptr = self.arenas[i].alloc(layout);
+            if !ptr.is_null() {
+                return ptr;
+            }
+        }
+        core::ptr::null_mut()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.arenas[self.arena_of(ptr)].dealloc(ptr, layout)
+    }
+
+    //only reallocates within the block's own arena; GrowableHeap moves the block if this fails
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.arenas[self.arena_of(ptr)].realloc(ptr, layout, new_size)
+    }
+}
+
+impl HeapBackend for GoodMemoryArenas {
+    const NAME: &'static str = "good_memory_allocator";
+
+    unsafe fn add_region(&self, start: usize, size: usize) {
+        let count = self.arena_count.load(Ordering::Acquire);
+        assert!(count < MAX_ARENAS, "kernel heap is out of arenas");
+        self.arenas[count].init(start, size);
+        self.arena_starts[count].store(start, Ordering::Relaxed);
+        self.arena_starts[count + 1].store(start + size, Ordering::Relaxed);
+        self.arena_count.store(count + 1, Ordering::Release);
+    }
+}
@@ -0,0 +1,23 @@
+use linked_list_allocator::LockedHeap;
+
+use super::HeapBackend;
+
+pub type Backend = LockedHeap;
+
+pub const fn new_backend() -> Backend {
+    LockedHeap::empty()
+}
+
+//linked_list_allocator keeps a list of free holes and uses the first one that fits
+impl HeapBackend for LockedHeap {
+    const NAME: &'static str = "linked_list_allocator";
+
+    unsafe fn add_region(&self, start: usize, size: usize) {
+        let mut heap = self.lock();
+        if heap.size() == 0 {
+            heap.init(start as *mut u8, size);
+        } else {
+            heap.extend(size); //regions are contiguous, so the existing heap just gets longer
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Number of buckets in the size-class histogram. Bucket `i` counts allocations of up to
+/// `16 << i` bytes; the last bucket takes everything larger.
+pub const SIZE_CLASSES: usize = 10;
+
+/// A [GlobalAlloc] that forwards to `A` and keeps count of what goes through it.
+///
+/// All counters are atomics, so the wrapper adds no lock of its own and
+/// [TrackingAllocator::stats] can be called from anywhere, even while the heap is busy.
+pub struct TrackingAllocator<A> {
+    inner: A,
+    live_allocations: AtomicUsize,
+    bytes_in_use: AtomicUsize,
+    peak_bytes_in_use: AtomicUsize,
+    total_allocations: AtomicUsize,
+    total_deallocations: AtomicUsize,
+    failed_allocations: AtomicUsize,
+    size_classes: [AtomicUsize; SIZE_CLASSES],
+}
+
+impl<A> TrackingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        TrackingAllocator {
+            inner,
+            live_allocations: AtomicUsize::new(0),
+            bytes_in_use: AtomicUsize::new(0),
+            peak_bytes_in_use: AtomicUsize::new(0),
+            total_allocations: AtomicUsize::new(0),
+            total_deallocations: AtomicUsize::new(0),
+            failed_allocations: AtomicUsize::new(0),
+            size_classes: [const { AtomicUsize::new(0) }; SIZE_CLASSES],
+        }
+    }
+
+    /// The allocator being tracked.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// A snapshot of the counters.
+    pub fn stats(&self) -> HeapStats {
+        let mut size_classes = [0; SIZE_CLASSES];
+        for (count, class) in size_classes.iter_mut().zip(self.size_classes.iter()) {
+            *count = class.load(Ordering::Relaxed);
+        }
+        HeapStats {
+            live_allocations: self.live_allocations.load(Ordering::Relaxed),
+            bytes_in_use: self.bytes_in_use.load(Ordering::Relaxed),
+            peak_bytes_in_use: self.peak_bytes_in_use.load(Ordering::Relaxed),
+            total_allocations: self.total_allocations.load(Ordering::Relaxed),
+            total_deallocations: self.total_deallocations.load(Ordering::Relaxed),
+            failed_allocations: self.failed_allocations.load(Ordering::Relaxed),
+            size_classes,
+        }
+    }
+
+    /// Restarts peak tracking from the current usage, e.g. before measuring one demo.
+    pub fn reset_peak(&self) {
+        let in_use = self.bytes_in_use.load(Ordering::Relaxed);
+        self.peak_bytes_in_use.store(in_use, Ordering::Relaxed);
+    }
+
+    fn record_alloc(&self, size: usize) {
+        self.live_allocations.fetch_add(1, Ordering::Relaxed);
+        self.total_allocations.fetch_add(1, Ordering::Relaxed);
+        self.size_classes[size_class(size)].fetch_add(1, Ordering::Relaxed);
+        self.add_bytes(size);
+    }
+
+    fn record_dealloc(&self, size: usize) {
+        self.live_allocations.fetch_sub(1, Ordering::Relaxed);
+        self.total_deallocations.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in_use.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    fn add_bytes(&self, size: usize) {
+        let in_use = self.bytes_in_use.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak_bytes_in_use.fetch_max(in_use, Ordering::Relaxed);
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if ptr.is_null() {
+            self.failed_allocations.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if new_ptr.is_null() {
+            self.failed_allocations.fetch_add(1, Ordering::Relaxed);
+            return new_ptr;
+        }
+        //same live allocation, new size
+        self.size_classes[size_class(new_size)].fetch_add(1, Ordering::Relaxed);
+        if new_size >= layout.size() {
+            self.add_bytes(new_size - layout.size());
+        } else {
+            self.bytes_in_use.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+fn size_class(size: usize) -> usize {
+    let mut class = 0;
+    while class < SIZE_CLASSES - 1 && size > 16 << class {
+        class += 1;
+    }
+    class
+}
+
+/// A snapshot of the heap counters. See [TrackingAllocator::stats].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    pub live_allocations: usize,
+    pub bytes_in_use: usize,
+    pub peak_bytes_in_use: usize,
+    pub total_allocations: usize,
+    pub total_deallocations: usize,
+    pub failed_allocations: usize,
+    /// Allocations made so far per size class (reallocations count under their new size).
+    pub size_classes: [usize; SIZE_CLASSES],
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            " live allocations: {}, bytes in use: {}, peak: {}",
+            self.live_allocations, self.bytes_in_use, self.peak_bytes_in_use
+        )?;
+        writeln!(
+            f,
+            " allocations: {}, deallocations: {}, failed: {}",
+            self.total_allocations, self.total_deallocations, self.failed_allocations
+        )?;
+        write!(f, " size classes:")?;
+        for (class, count) in self.size_classes.iter().enumerate() {
+            if class == SIZE_CLASSES - 1 {
+                write!(f, " >{}: {}", 16 << (class - 1), count)?;
+            } else {
+                write!(f, " <={}: {}", 16 << class, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The heap state at some point in time, to compare against later.
+///
+/// ```ignore
+/// let mark = allocator::heap_mark();
+/// box_vs_rc();
+/// assert!(mark.compare().is_balanced(), "box_vs_rc leaked: {}", mark.compare());
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct HeapMark {
+    stats: HeapStats,
+}
+
+impl HeapMark {
+    pub fn new(stats: HeapStats) -> Self {
+        HeapMark { stats }
+    }
+
+    /// What changed since the mark was taken, measured against the global allocator.
+    pub fn compare(&self) -> HeapDelta {
+        self.compare_with(&super::heap_stats())
+    }
+
+    pub fn compare_with(&self, now: &HeapStats) -> HeapDelta {
+        HeapDelta {
+            live_allocations: now.live_allocations as isize - self.stats.live_allocations as isize,
+            bytes_in_use: now.bytes_in_use as isize - self.stats.bytes_in_use as isize,
+            allocations_made: now.total_allocations - self.stats.total_allocations,
+            peak_bytes_in_use: now.peak_bytes_in_use,
+        }
+    }
+}
+
+/// Difference between a [HeapMark] and a later state of the heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapDelta {
+    /// Allocations still alive that were not there at the mark (negative: more were freed).
+    pub live_allocations: isize,
+    pub bytes_in_use: isize,
+    /// Allocations made in between, whether freed or not.
+    pub allocations_made: usize,
+    /// Peak bytes in use since the most recent [super::heap_mark] (or [TrackingAllocator::reset_peak]).
+    pub peak_bytes_in_use: usize,
+}
+
+impl HeapDelta {
+    /// True if the heap is back to where it was at the mark.
+    pub fn is_balanced(&self) -> bool {
+        self.live_allocations == 0 && self.bytes_in_use == 0
+    }
+}
+
+impl fmt::Display for HeapDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_balanced() {
+            write!(f, "balanced ({} allocations made and freed)", self.allocations_made)
+        } else {
+            write!(
+                f,
+                "{:+} allocations and {:+} bytes still live ({} allocations made)",
+                self.live_allocations, self.bytes_in_use, self.allocations_made
+            )
+        }
+    }
+}
@@ -0,0 +1,144 @@
+/*Stack backtraces for panics and exceptions.
+The kernel is built with frame pointers (see os_with_bootloader/.cargo/config.toml), so every function starts with
+    push rbp
+    mov rbp, rsp
+which makes rbp point at the caller's saved rbp, with the return address right above it. Following that chain
+walks the stack from the innermost function outwards.
+Return addresses are turned into function names with the symbol table that os_with_bootloader/build.rs
+writes from the kernel ELF and hands to the bootloader as the ramdisk.
+Nothing here allocates or takes locks, because it runs from the panic handler.
+Ref: https://doc.rust-lang.org/rustc/codegen-options/index.html#force-frame-pointers */
+
+use core::fmt;
+
+use spin::Once;
+use x86_64::VirtAddr;
+
+use crate::allocator::{self, HEAP_START};
+use crate::gdt;
+use crate::memory::layout;
+
+/// Frames deeper than this are not shown.
+pub const MAX_FRAMES: usize = 32;
+
+//The symbol table text: "<start hex> <size hex> <name>" per line, sorted by start address
+static SYMBOLS: Once<&'static str> = Once::new();
+
+/// Remembers where the bootloader loaded the symbol table (the ramdisk).
+///
+/// # Safety
+/// `ramdisk_addr` must point to `ramdisk_len` bytes that stay mapped and unchanged for as long as the kernel runs.
+pub unsafe fn init(ramdisk_addr: Option<u64>, ramdisk_len: u64) {
+    if let Some(addr) = ramdisk_addr {
+        let bytes = core::slice::from_raw_parts(addr as *const u8, ramdisk_len as usize);
+        if let Ok(text) = core::str::from_utf8(bytes) {
+            SYMBOLS.call_once(|| text);
+        }
+    }
+}
+
+/// The function containing `addr` and how far into the function `addr` is.
+pub fn symbolize(addr: u64) -> Option<(&'static str, u64)> {
+    let symbols = SYMBOLS.get()?;
+    //the table has the addresses of the ELF file, the kernel was loaded at kernel_image_offset
+    let image_start = layout::layout()?.kernel_image.start.as_u64();
+    let elf_addr = addr.checked_sub(image_start)?;
+
+    let mut found = None;
+    for line in symbols.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (Some(start), Some(size), Some(name)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let (Ok(start), Ok(size)) = (u64::from_str_radix(start, 16), u64::from_str_radix(size, 16)) else {
+            continue;
+        };
+        if start > elf_addr {
+            break; //sorted, so nothing further down can contain the address
+        }
+        if elf_addr < start + size.max(1) {
+            found = Some((name, elf_addr - start));
+        }
+    }
+    found
+}
+
+//rbp must point into one of the stacks we know about before we read from it
+fn is_stack_address(addr: u64) -> bool {
+    let addr = VirtAddr::new_truncate(addr);
+    let on_kernel_stack = layout::layout().is_some_and(|layout| layout.kernel_stack.contains(&addr));
+    let heap_start = HEAP_START as u64;
+    let on_heap = (heap_start..heap_start + allocator::ALLOCATOR.inner().mapped_size() as u64).contains(&addr.as_u64());
+    on_kernel_stack || gdt::is_ist_stack_address(addr) || on_heap
+}
+
+/// A captured chain of return addresses.
+pub struct Backtrace {
+    frames: [u64; MAX_FRAMES],
+    len: usize,
+    //frames[0] is the exact faulting instruction rather than a return address
+    starts_at_instruction: bool,
+}
+
+impl Backtrace {
+    /// Captures the stack of the caller.
+    #[inline(never)]
+    pub fn capture() -> Backtrace {
+        let rbp: u64;
+        unsafe {
+            core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+        }
+        let mut backtrace = Backtrace { frames: [0; MAX_FRAMES], len: 0, starts_at_instruction: false };
+        backtrace.walk(rbp);
+        backtrace
+    }
+
+    /// Captures the stack of interrupted code, e.g. from an exception's stack frame and saved rbp.
+    pub fn from_frame(instruction_pointer: VirtAddr, rbp: u64) -> Backtrace {
+        let mut backtrace = Backtrace { frames: [0; MAX_FRAMES], len: 1, starts_at_instruction: true };
+        backtrace.frames[0] = instruction_pointer.as_u64();
+        backtrace.walk(rbp);
+        backtrace
+    }
+
+    fn walk(&mut self, mut rbp: u64) {
+        while self.len < MAX_FRAMES {
+            if rbp == 0 || !rbp.is_multiple_of(8) || !is_stack_address(rbp) || !is_stack_address(rbp + 8) {
+                break;
+            }
+            let (saved_rbp, return_address) = unsafe { (*(rbp as *const u64), *((rbp + 8) as *const u64)) };
+            if return_address == 0 {
+                break;
+            }
+            self.frames[self.len] = return_address;
+            self.len += 1;
+            //callers' frames are higher up the stack; anything else means the chain is broken
+            if saved_rbp <= rbp {
+                break;
+            }
+            rbp = saved_rbp;
+        }
+    }
+
+    pub fn frames(&self) -> &[u64] {
+        &self.frames[..self.len]
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " Backtrace:")?;
+        if SYMBOLS.get().is_none() {
+            write!(f, " (no symbol table in the ramdisk, addresses only)")?;
+        }
+        for (index, &address) in self.frames().iter().enumerate() {
+            //a return address is just past the call, which may already be the next function
+            let lookup = if index == 0 && self.starts_at_instruction { address } else { address - 1 };
+            write!(f, "\n  #{:<2} {:#x}", index, address)?;
+            if let Some((name, offset)) = symbolize(lookup) {
+                write!(f, " {}+{:#x}", name, offset + (address - lookup))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+/*Here we setup the Global Descriptor Table (GDT) and the Task State Segment (TSS).
+The TSS holds the Interrupt Stack Table (IST): up to 7 known-good stacks the CPU switches to
+before calling a handler. Without it, a kernel stack overflow hits the guard page, the page fault
+can't push its frame on the same broken stack, the double fault can't either, and the CPU
+triple-faults (QEMU just reboots).
+Ref: https://os.phil-opp.com/double-fault-exceptions/#switching-stacks */
+
+use core::ptr::addr_of;
+
+use lazy_static::lazy_static;
+use x86_64::instructions::segmentation::{Segment, CS, DS, ES, SS};
+use x86_64::instructions::tables::load_tss;
+use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::tss::TaskStateSegment;
+use x86_64::VirtAddr;
+
+//IST slots used by interrupts.rs. Each exception that may arrive on a broken stack gets its own
+//stack, so e.g. an NMI during a double fault doesn't overwrite the double fault's frame.
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const NMI_IST_INDEX: u16 = 1;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+
+//The handlers panic and print through the framebuffer writer, so give them a bit more than phil-opp's 20 KiB
+const IST_STACK_SIZE: usize = 4096 * 8;
+
+//The stacks are just static arrays. They are never touched by Rust code, only by the CPU
+//(which aligns the stack pointer to 16 bytes itself when it switches to an IST stack)
+static mut DOUBLE_FAULT_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+static mut NMI_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+static mut MACHINE_CHECK_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+
+/// Whether `addr` is inside one of the IST stacks (used by the backtrace code to check frame pointers).
+pub fn is_ist_stack_address(addr: VirtAddr) -> bool {
+    [addr_of!(DOUBLE_FAULT_STACK), addr_of!(NMI_STACK), addr_of!(MACHINE_CHECK_STACK)]
+        .into_iter()
+        .any(|stack| VirtAddr::from_ptr(stack) <= addr && addr < stack_top(stack))
+}
+
+//stacks grow downwards, so the IST entry must point at the end of the array
+fn stack_top(stack: *const [u8; IST_STACK_SIZE]) -> VirtAddr {
+    VirtAddr::from_ptr(stack) + IST_STACK_SIZE as u64
+}
+
+lazy_static! {
+    static ref TSS: TaskStateSegment = {
+        let mut tss = TaskStateSegment::new();
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = stack_top(addr_of!(DOUBLE_FAULT_STACK));
+        tss.interrupt_stack_table[NMI_IST_INDEX as usize] = stack_top(addr_of!(NMI_STACK));
+        tss.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] = stack_top(addr_of!(MACHINE_CHECK_STACK));
+        tss
+    };
+}
+
+struct Selectors {
+    code_selector: SegmentSelector,
+    data_selector: SegmentSelector,
+    tss_selector: SegmentSelector,
+}
+
+lazy_static! {
+    static ref GDT: (GlobalDescriptorTable, Selectors) = {
+        let mut gdt = GlobalDescriptorTable::new();
+        let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+        let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        (gdt, Selectors { code_selector, data_selector, tss_selector })
+    };
+}
+
+//Load our GDT and TSS. Call this before the IDT is loaded, since the IDT entries refer to the IST
+pub fn init() {
+    GDT.0.load();
+    unsafe {
+        //the bootloader's segment selectors point into its own GDT, so reload all of them
+        CS::set_reg(GDT.1.code_selector);
+        SS::set_reg(GDT.1.data_selector);
+        DS::set_reg(GDT.1.data_selector);
+        ES::set_reg(GDT.1.data_selector);
+        load_tss(GDT.1.tss_selector);
+    }
+}
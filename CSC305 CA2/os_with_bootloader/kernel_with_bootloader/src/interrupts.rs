@@ -1,52 +1,28 @@
+pub mod exceptions;
+pub mod page_fault;
+
 use alloc::string::String;
 use x86_64::structures::idt::InterruptStackFrame;
 use x86_64::structures::idt::InterruptDescriptorTable;
 
-use crate::print;
-use crate::println;//use your custom println macro.
-
-/*In this section we define handlers for interrupts*/
-//1. breakpoint_handler - handles the invocation of INT3
-extern "x86-interrupt" fn breakpoint_handler(
-    stack_frame: InterruptStackFrame)
-{
-    println!("EXCEPTION: BREAKPOINT\n Stack Frame:\n {:#?}", stack_frame);
-}
-
-//2. double_fault_handler
-extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame, _error_code: u64) -> !
-{
-    panic!("EXCEPTION: DOUBLE FAULT\n Stack Frame:\n{:#?}", stack_frame);
-}
-
-//3. General protection handler
-extern "x86-interrupt" fn general_protection_handler(
-    stack_frame: InterruptStackFrame, _error_code: u64)
-{
-    println!("EXCEPTION: GENERAL PROTECTION\n Error Code: {:#?}\n Stack Frame:\n{:#?}", _error_code, stack_frame);
-}
-
-//4. Invalid opcode handler
-extern "x86-interrupt" fn invalid_opcode_handler(
-    stack_frame: InterruptStackFrame)
-{
-    println!("EXCEPTION: INVALID OPCODE\n Stack Frame:\n {:#?}", stack_frame);
-}
+use crate::sync::IrqSafeMutex;
 
+/*The CPU exception handlers (divide error, breakpoint, double fault, page fault, ...) are in exceptions.rs.
+Each one prints the exception name, the decoded error code, the stack frame and the registers*/
 
 /*Here we setup our Programmable Interrupt Controller
 Ref: Class slides and https://os.phil-opp.com/hardware-interrupts*/
 
 use pic8259::ChainedPics;
-use spin;
 
 //set the offset of the pics
 const PIC_1_OFFSET: u8 = 32;
 const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
-static PICS: spin::Mutex<ChainedPics> =
-    spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+//Everything shared with interrupt handlers is behind an IrqSafeMutex (sync/irq_mutex.rs): while it is locked,
+//interrupts are off, so a handler can never find it locked and spin forever
+static PICS: IrqSafeMutex<ChainedPics> =
+    IrqSafeMutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
 //initialize PICS
 fn init_pics(){
@@ -76,63 +52,28 @@ impl InterruptIndex {
     }
 }
 //Add a handler for Timer
-extern "x86-interrupt" fn timer_interrupt_handler(
-    _stack_frame: InterruptStackFrame)
-{
+//The timer interrupt also switches between threads, so its entry point is a naked stub in thread/switch.rs
+//that saves all registers. This is the part that runs on every tick, before the scheduler
+pub(crate) fn timer_tick() {
     //print!("."); //You can uncomment this to see that timer interrupt is on.
+    crate::time::tick();
+    crate::task::timer::wake_expired(); //wake tasks waiting in task::sleep()
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
     }
 }
-//Below is to hold globally any unicode key pressed on keyboard. It is used 
-//in the keyboard_interrupt_handler function below. 
-//Rather than just keep echoing to screen immediately, we save off in a global variable
-//See input_str function in std.rs for how I interact with it
-//See main.rs from lines 156 to 161 for how I called the input_str
-lazy_static! {
-    pub(crate) static ref KEY_PRESSED: spin::Mutex<Option<char>> =
-        spin::Mutex::new(None);
-}
 //Add a handler for keyboard
+//It only reads the scancode and hands it to keyboard.rs, which queues the key event without taking a lock.
+//Decoding into characters happens when the key is read, see input_str in std.rs
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
-    use spin::Mutex;
     use x86_64::instructions::port::Port;
 
-    lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)
-        );
-    }
-
-    let mut keyboard = KEYBOARD.lock();
-    let mut port = Port::new(0x60);
-
-    let scancode: u8 = unsafe { port.read() };
-    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-        if let Some(key) = keyboard.process_keyevent(key_event) {
-            match key {
-                DecodedKey::Unicode(character) => {
-                    match character {
-                        /* Uncomment out below if what you what is immediate display on screen as you press keyboard. Else, let our std::input_str handle it
-                        '\u{0008}'  => {
-                            print!("{}", character);
-                            
-                        },*/
-                        _ => {
-                            unsafe{
-                                KEY_PRESSED.force_unlock();//Give keyboard interrupt absolute priority
-                            }
-                            *KEY_PRESSED.lock() = Some(character);
-                            //print!("{}", character); //Uncomment out this and comment out above (124 to 127) if what you what is immediate display on screen as you press keyboard. Else, let our std::input_str handle it
-                        }
-                    }
-                    
-                },
-                DecodedKey::RawKey(key) => print!("{:?}", key),
-            }
-        }
+    //nothing to read if keyboard::set_scancode_set() already took the controller's reply this interrupt was for
+    if crate::keyboard::ps2::has_data() {
+        let mut port = Port::new(0x60);
+        let scancode: u8 = unsafe { port.read() };
+        crate::keyboard::handle_scancode(scancode);
     }
 
     unsafe {
@@ -147,20 +88,16 @@ use lazy_static::lazy_static;
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
-        idt.double_fault.set_handler_fn(double_fault_handler);
-        idt.general_protection_fault.set_handler_fn(general_protection_handler);
-        idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
-        idt[InterruptIndex::Timer.as_usize()]
-            .set_handler_fn(timer_interrupt_handler); 
+        exceptions::set_handlers(&mut idt);
+        crate::thread::switch::set_handlers(&mut idt); //timer, and the yield interrupt threads use
         idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
         idt
     };
 }
 
 //Below function to be called from init() at the bottom of this 
-//this module, to init IDT.
-fn init_idt(){
+//this module, to init IDT. main.rs also calls it early so exceptions are reported during boot.
+pub fn init_idt(){
     IDT.load();
 }
 
@@ -168,6 +105,7 @@ fn init_idt(){
 pub fn init() {
     init_idt(); //IDT
     init_pics(); //PICS
+    crate::time::init(crate::time::DEFAULT_FREQUENCY_HZ); //PIT: timer interrupt every millisecond
     x86_64::instructions::interrupts::enable_and_hlt();//enable hardware interrupts. Without handler for timer interrupt, which is on by default, there will be a double fault
 }
 
@@ -0,0 +1,329 @@
+/*Handlers for all the CPU exceptions (vectors 0 to 31).
+The "x86-interrupt" calling convention only gives us the interrupt stack frame, not the general purpose
+registers of the interrupted code, and those are what you want to see when something crashes.
+So every exception gets a small naked-assembly trampoline instead: it pushes the vector number
+(and a 0 where the CPU pushes no error code) and jumps to a common stub, which saves all general purpose
+registers and calls exception_dispatch with a pointer to everything it pushed. If the dispatcher returns,
+the stub restores the registers and returns from the interrupt.
+Ref: https://os.phil-opp.com/cpu-exceptions/ and the Intel SDM Vol. 3A, chapter 6 */
+
+use core::arch::naked_asm;
+use core::fmt;
+
+use x86_64::structures::idt::{
+    InterruptDescriptorTable, InterruptStackFrameValue, PageFaultErrorCode, SelectorErrorCode,
+};
+use x86_64::VirtAddr;
+
+use super::page_fault;
+use crate::backtrace::{self, Backtrace};
+use crate::gdt;
+use crate::println;
+
+/// The general purpose registers at the time of the exception, in the order the common stub pushes them
+/// (so the last one pushed comes first).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+/// Everything on the stack when exception_dispatch is called: our pushes, then the CPU's.
+#[repr(C)]
+pub struct ExceptionContext {
+    pub registers: Registers,
+    pub vector: u64,
+    /// 0 for exceptions that have no error code.
+    pub error_code: u64,
+    pub stack_frame: InterruptStackFrameValue,
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, " rax={:#018x} rbx={:#018x} rcx={:#018x}", self.rax, self.rbx, self.rcx)?;
+        writeln!(f, " rdx={:#018x} rsi={:#018x} rdi={:#018x}", self.rdx, self.rsi, self.rdi)?;
+        writeln!(f, " rbp={:#018x} r8 ={:#018x} r9 ={:#018x}", self.rbp, self.r8, self.r9)?;
+        writeln!(f, " r10={:#018x} r11={:#018x} r12={:#018x}", self.r10, self.r11, self.r12)?;
+        write!(f, " r13={:#018x} r14={:#018x} r15={:#018x}", self.r13, self.r14, self.r15)
+    }
+}
+
+/// How the error code of an exception is to be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorCode {
+    None,
+    /// Always 0 (double fault, alignment check).
+    Zero,
+    /// A segment selector index (invalid TSS, segment not present, stack segment and GP faults).
+    Selector,
+    PageFault,
+    /// Some other bit field; printed as a number.
+    Raw,
+}
+
+/// What happens after the report is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// Print the report and carry on (e.g. int3).
+    Continue,
+    Panic,
+}
+
+struct Exception {
+    name: &'static str,
+    error_code: ErrorCode,
+    outcome: Outcome,
+}
+
+const fn exception(name: &'static str, error_code: ErrorCode, outcome: Outcome) -> Exception {
+    Exception { name, error_code, outcome }
+}
+
+const RESERVED: Exception = exception("RESERVED", ErrorCode::None, Outcome::Panic);
+
+//vector number -> exception. Intel SDM Vol. 3A, table 6-1
+static EXCEPTIONS: [Exception; 32] = [
+    exception("DIVIDE ERROR", ErrorCode::None, Outcome::Panic),
+    exception("DEBUG", ErrorCode::None, Outcome::Continue),
+    exception("NON-MASKABLE INTERRUPT", ErrorCode::None, Outcome::Continue),
+    exception("BREAKPOINT", ErrorCode::None, Outcome::Continue),
+    exception("OVERFLOW", ErrorCode::None, Outcome::Panic),
+    exception("BOUND RANGE EXCEEDED", ErrorCode::None, Outcome::Panic),
+    exception("INVALID OPCODE", ErrorCode::None, Outcome::Panic),
+    exception("DEVICE NOT AVAILABLE", ErrorCode::None, Outcome::Panic),
+    exception("DOUBLE FAULT", ErrorCode::Zero, Outcome::Panic),
+    RESERVED, //coprocessor segment overrun, not used since the 486
+    exception("INVALID TSS", ErrorCode::Selector, Outcome::Panic),
+    exception("SEGMENT NOT PRESENT", ErrorCode::Selector, Outcome::Panic),
+    exception("STACK SEGMENT FAULT", ErrorCode::Selector, Outcome::Panic),
+    exception("GENERAL PROTECTION", ErrorCode::Selector, Outcome::Panic),
+    exception("PAGE FAULT", ErrorCode::PageFault, Outcome::Panic),
+    RESERVED,
+    exception("X87 FLOATING POINT", ErrorCode::None, Outcome::Panic),
+    exception("ALIGNMENT CHECK", ErrorCode::Zero, Outcome::Panic),
+    exception("MACHINE CHECK", ErrorCode::None, Outcome::Panic),
+    exception("SIMD FLOATING POINT", ErrorCode::None, Outcome::Panic),
+    exception("VIRTUALIZATION", ErrorCode::None, Outcome::Panic),
+    exception("CONTROL PROTECTION", ErrorCode::Raw, Outcome::Panic),
+    RESERVED,
+    RESERVED,
+    RESERVED,
+    RESERVED,
+    RESERVED,
+    RESERVED,
+    exception("HYPERVISOR INJECTION", ErrorCode::None, Outcome::Panic),
+    exception("VMM COMMUNICATION", ErrorCode::Raw, Outcome::Panic),
+    exception("SECURITY", ErrorCode::Raw, Outcome::Panic),
+    RESERVED,
+];
+
+/// The report printed for every exception: name, error code, stack frame and registers.
+pub struct ExceptionReport<'a> {
+    context: &'a ExceptionContext,
+}
+
+impl fmt::Display for ExceptionReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let context = self.context;
+        let exception = &EXCEPTIONS[context.vector as usize];
+        writeln!(f, "EXCEPTION: {} (vector {})", exception.name, context.vector)?;
+        match exception.error_code {
+            ErrorCode::None | ErrorCode::Zero => {}
+            ErrorCode::Selector => {
+                let selector = SelectorErrorCode::new_truncate(context.error_code);
+                if selector.is_null() {
+                    writeln!(f, " Error Code: 0 (not caused by a segment selector)")?;
+                } else {
+                    writeln!(
+                        f,
+                        " Error Code: {:#x} (selector index {} in the {:?}, external: {})",
+                        context.error_code,
+                        selector.index(),
+                        selector.descriptor_table(),
+                        selector.external()
+                    )?;
+                }
+            }
+            ErrorCode::PageFault => writeln!(
+                f,
+                " Error Code: {:?}",
+                PageFaultErrorCode::from_bits_truncate(context.error_code)
+            )?,
+            ErrorCode::Raw => writeln!(f, " Error Code: {:#x}", context.error_code)?,
+        }
+        if let Some((name, offset)) = backtrace::symbolize(context.stack_frame.instruction_pointer.as_u64()) {
+            writeln!(f, " In: {}+{:#x}", name, offset)?;
+        }
+        writeln!(f, " Stack Frame:\n{:#?}", context.stack_frame)?;
+        write!(f, " Registers:\n{}", context.registers)
+    }
+}
+
+extern "C" fn exception_dispatch(context: &mut ExceptionContext) {
+    let report = ExceptionReport { context };
+
+    if context.vector == 14 {
+        //the page fault policy may fix the mapping, in which case the instruction is retried
+        let error_code = PageFaultErrorCode::from_bits_truncate(context.error_code);
+        if let Some(fault) = page_fault::handle(&context.stack_frame, error_code) {
+            panic!("{}\n{}", report, fault);
+        }
+        return;
+    }
+    if context.vector == 8 {
+        if let Some(address) = page_fault::stack_overflow() {
+            panic!("{}\n Address: {:#x} (KernelStackGuard)\n{}", report, address.as_u64(), page_fault::STACK_OVERFLOW);
+        }
+    }
+
+    match EXCEPTIONS[context.vector as usize].outcome {
+        //when panicking, the panic handler prints the backtrace (through this function and the trampoline)
+        Outcome::Continue => println!(
+            "{}\n{}",
+            report,
+            Backtrace::from_frame(context.stack_frame.instruction_pointer, context.registers.rbp)
+        ),
+        Outcome::Panic => panic!("{}", report),
+    }
+}
+
+//Saves the registers, calls exception_dispatch, restores the registers and returns from the interrupt.
+//Stack on entry: vector, error code, interrupt stack frame. The CPU aligned the stack to 16 bytes before
+//pushing its 5 (or 6 with error code) words, so after our pushes (2 + 15 words) the call is aligned again.
+#[unsafe(naked)]
+extern "C" fn common_stub() {
+    naked_asm!(
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov rdi, rsp", //&mut ExceptionContext
+        "cld",          //the System V ABI expects the direction flag to be clear
+        "call {dispatch}",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        "add rsp, 16", //vector and error code
+        "iretq",
+        dispatch = sym exception_dispatch,
+    );
+}
+
+//One trampoline per exception. Exceptions without an error code push a 0 so the stack looks the same for all
+macro_rules! trampoline {
+    ($name:ident, $vector:literal) => {
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            naked_asm!("push 0", "push {vector}", "jmp {common}", vector = const $vector, common = sym common_stub);
+        }
+    };
+    ($name:ident, $vector:literal, error_code) => {
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            naked_asm!("push {vector}", "jmp {common}", vector = const $vector, common = sym common_stub);
+        }
+    };
+}
+
+trampoline!(divide_error, 0);
+trampoline!(debug, 1);
+trampoline!(non_maskable_interrupt, 2);
+trampoline!(breakpoint, 3);
+trampoline!(overflow, 4);
+trampoline!(bound_range_exceeded, 5);
+trampoline!(invalid_opcode, 6);
+trampoline!(device_not_available, 7);
+trampoline!(double_fault, 8, error_code);
+trampoline!(invalid_tss, 10, error_code);
+trampoline!(segment_not_present, 11, error_code);
+trampoline!(stack_segment_fault, 12, error_code);
+trampoline!(general_protection_fault, 13, error_code);
+trampoline!(page_fault, 14, error_code);
+trampoline!(x87_floating_point, 16);
+trampoline!(alignment_check, 17, error_code);
+trampoline!(machine_check, 18);
+trampoline!(simd_floating_point, 19);
+trampoline!(virtualization, 20);
+trampoline!(cp_protection_exception, 21, error_code);
+trampoline!(hv_injection_exception, 28);
+trampoline!(vmm_communication_exception, 29, error_code);
+trampoline!(security_exception, 30, error_code);
+
+fn addr(trampoline: extern "C" fn()) -> VirtAddr {
+    VirtAddr::new(trampoline as usize as u64)
+}
+
+/// Points every exception entry of `idt` at its trampoline.
+pub fn set_handlers(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        idt.divide_error.set_handler_addr(addr(divide_error));
+        idt.debug.set_handler_addr(addr(debug));
+        idt.breakpoint.set_handler_addr(addr(breakpoint));
+        idt.overflow.set_handler_addr(addr(overflow));
+        idt.bound_range_exceeded.set_handler_addr(addr(bound_range_exceeded));
+        idt.invalid_opcode.set_handler_addr(addr(invalid_opcode));
+        idt.device_not_available.set_handler_addr(addr(device_not_available));
+        idt.invalid_tss.set_handler_addr(addr(invalid_tss));
+        idt.segment_not_present.set_handler_addr(addr(segment_not_present));
+        idt.stack_segment_fault.set_handler_addr(addr(stack_segment_fault));
+        idt.general_protection_fault.set_handler_addr(addr(general_protection_fault));
+        idt.page_fault.set_handler_addr(addr(page_fault));
+        idt.x87_floating_point.set_handler_addr(addr(x87_floating_point));
+        idt.alignment_check.set_handler_addr(addr(alignment_check));
+        idt.simd_floating_point.set_handler_addr(addr(simd_floating_point));
+        idt.virtualization.set_handler_addr(addr(virtualization));
+        idt.cp_protection_exception.set_handler_addr(addr(cp_protection_exception));
+        idt.hv_injection_exception.set_handler_addr(addr(hv_injection_exception));
+        idt.vmm_communication_exception.set_handler_addr(addr(vmm_communication_exception));
+        idt.security_exception.set_handler_addr(addr(security_exception));
+
+        //these run on their own IST stacks (see gdt.rs), so they still work when the kernel stack
+        //is broken, e.g. after a stack overflow
+        idt.double_fault
+            .set_handler_addr(addr(double_fault))
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        idt.non_maskable_interrupt
+            .set_handler_addr(addr(non_maskable_interrupt))
+            .set_stack_index(gdt::NMI_IST_INDEX);
+        idt.machine_check
+            .set_handler_addr(addr(machine_check))
+            .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+    }
+}
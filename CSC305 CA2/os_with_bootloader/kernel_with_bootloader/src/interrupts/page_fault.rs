@@ -0,0 +1,229 @@
+/*Page fault handling.
+The CPU raises a page fault when an address isn't mapped or the mapping doesn't allow the access.
+It puts the faulting address in CR2 and pushes an error code that says what kind of access it was.
+We decode both, work out which part of the address space the address belongs to, and then let the
+fault policy decide: fix the mapping and retry the instruction, or panic with a readable report.
+Ref: https://os.phil-opp.com/paging-introduction/#page-faults */
+
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptStackFrameValue, PageFaultErrorCode};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+use crate::allocator::{self, HEAP_MAX_SIZE, HEAP_START};
+use crate::memory::{self, layout, paging::Translation};
+
+/// The part of the address space a faulting address falls in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// The first page. Almost always a null pointer (or a field of one) being dereferenced.
+    NullPage,
+    /// The unmapped page below the boot stack: the kernel stack overflowed.
+    KernelStackGuard,
+    KernelStack,
+    KernelImage,
+    /// The mapped part of the heap range.
+    Heap,
+    /// The heap range past what has been mapped so far. Nothing should point here.
+    UnmappedHeap,
+    /// The bootloader's mapping of all physical memory.
+    PhysicalMemory,
+    /// Lower half of the address space, where user programs would live.
+    UserSpace,
+    Unknown,
+}
+
+impl Region {
+    pub fn of(addr: VirtAddr) -> Region {
+        let address = addr.as_u64();
+        if address < 4096 {
+            return Region::NullPage;
+        }
+
+        let heap_start = HEAP_START as u64;
+        let heap_end = heap_start + allocator::ALLOCATOR.inner().mapped_size() as u64;
+        if (heap_start..heap_end).contains(&address) {
+            return Region::Heap;
+        }
+        if (heap_end..heap_start + HEAP_MAX_SIZE as u64).contains(&address) {
+            return Region::UnmappedHeap;
+        }
+
+        if let Some(layout) = layout::layout() {
+            if layout.kernel_stack_guard.start_address() <= addr
+                && addr < layout.kernel_stack_guard.start_address() + 4096u64
+            {
+                return Region::KernelStackGuard;
+            }
+            if layout.kernel_stack.contains(&addr) {
+                return Region::KernelStack;
+            }
+            if layout.kernel_image.contains(&addr) {
+                return Region::KernelImage;
+            }
+            if layout.physical_memory.contains(&addr) {
+                return Region::PhysicalMemory;
+            }
+        }
+
+        if address < 0x0000_8000_0000_0000 {
+            Region::UserSpace
+        } else {
+            Region::Unknown
+        }
+    }
+}
+
+/// Everything we know about one page fault.
+#[derive(Debug, Clone, Copy)]
+pub struct PageFault {
+    /// The address that was accessed (from CR2).
+    pub address: VirtAddr,
+    pub error_code: PageFaultErrorCode,
+    /// The instruction that faulted.
+    pub instruction_pointer: VirtAddr,
+    pub region: Region,
+    /// The current mapping of `address`, if it has one (then the fault was a protection violation).
+    /// None also when the page tables were locked at the time of the fault.
+    pub translation: Option<Translation>,
+}
+
+impl PageFault {
+    pub fn is_write(&self) -> bool {
+        self.error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE)
+    }
+
+    pub fn is_instruction_fetch(&self) -> bool {
+        self.error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH)
+    }
+
+    /// The page was mapped, but the access wasn't allowed (e.g. a write to a read-only page).
+    pub fn is_protection_violation(&self) -> bool {
+        self.error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+    }
+}
+
+impl fmt::Display for PageFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = if self.is_instruction_fetch() {
+            "instruction fetch"
+        } else if self.is_write() {
+            "write"
+        } else {
+            "read"
+        };
+        let cause = if self.is_protection_violation() {
+            "protection violation"
+        } else {
+            "page not present"
+        };
+        let mode = if self.error_code.contains(PageFaultErrorCode::USER_MODE) {
+            "user"
+        } else {
+            "kernel"
+        };
+        writeln!(f, " Address: {:#x} ({:?})", self.address.as_u64(), self.region)?;
+        writeln!(f, " Access: {} in {} mode, {}", access, mode, cause)?;
+        if self.error_code.contains(PageFaultErrorCode::MALFORMED_TABLE) {
+            writeln!(f, " A page table entry has a reserved bit set")?;
+        }
+        match &self.translation {
+            Some(translation) => writeln!(
+                f,
+                " Mapped to: {:#x} with {:?}",
+                translation.phys_addr.as_u64(),
+                translation.flags
+            )?,
+            None => writeln!(f, " Mapped to: nothing")?,
+        }
+        if self.region == Region::KernelStackGuard {
+            writeln!(f, "{}", STACK_OVERFLOW)?;
+        }
+        write!(f, " Instruction: {:#x}", self.instruction_pointer.as_u64())
+    }
+}
+
+pub(super) const STACK_OVERFLOW: &str = " The kernel stack overflowed (see kernel_stack_size in main.rs)";
+
+/// What the page fault handler should do once the policy has looked at the fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultAction {
+    /// The policy fixed the mapping. Return and let the CPU retry the instruction.
+    Retry,
+    /// The fault can't be fixed. Panic with the exception report.
+    Panic,
+}
+
+/// Decides what happens on a page fault. Runs inside the exception handler, so it must not
+/// take locks that the faulting code might be holding.
+pub type FaultPolicy = fn(&PageFault) -> FaultAction;
+
+/// The default policy: every page fault is a bug.
+pub fn panic_policy(_fault: &PageFault) -> FaultAction {
+    FaultAction::Panic
+}
+
+static FAULT_POLICY: Mutex<FaultPolicy> = Mutex::new(panic_policy);
+
+/// Installs `policy` and returns the one it replaces (so it can be put back, or called as a fallback).
+pub fn set_fault_policy(policy: FaultPolicy) -> FaultPolicy {
+    core::mem::replace(&mut *FAULT_POLICY.lock(), policy)
+}
+
+/// Maps the faulting page to a fresh zeroed frame, for policies that want demand paging
+/// in a range of their own. Must not be used for addresses that are already mapped.
+pub fn map_faulting_page(fault: &PageFault) -> FaultAction {
+    let page_start = fault.address.align_down(4096u64);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    //try_lock both: if the fault happened while the page tables were being edited, or while frames were being
+    //handed out (e.g. the heap growing), we can't fix it, and waiting for the lock would never end
+    let mapped = match (memory::MAPPER.try_lock(), memory::FRAME_ALLOCATOR.try_lock()) {
+        (Some(mut mapper), Some(mut frames)) => match (mapper.as_mut(), frames.as_mut()) {
+            (Some(mapper), Some(frames)) => mapper.map_range(page_start, 4096, flags, frames).is_ok(),
+            _ => false,
+        },
+        _ => false,
+    };
+    if mapped {
+        unsafe { core::ptr::write_bytes(page_start.as_mut_ptr::<u8>(), 0, 4096) };
+        FaultAction::Retry
+    } else {
+        FaultAction::Panic
+    }
+}
+
+/// Called by the exception dispatcher (exceptions.rs) for a double fault. When the kernel stack overflows, the
+/// page fault on the guard page can't push its frame on that same stack, so the CPU raises a double fault
+/// instead (which has an IST stack), and handle() never runs. CR2 still holds the page fault's address.
+/// Returns it if it is in the guard page.
+pub(super) fn stack_overflow() -> Option<VirtAddr> {
+    let address = Cr2::read();
+    (Region::of(address) == Region::KernelStackGuard).then_some(address)
+}
+
+/// Called by the exception dispatcher (exceptions.rs) for vector 14.
+/// Returns None if the policy fixed the fault, or the fault to panic with.
+pub(super) fn handle(stack_frame: &InterruptStackFrameValue, error_code: PageFaultErrorCode) -> Option<PageFault> {
+    let address = Cr2::read();
+    let translation = match memory::MAPPER.try_lock() {
+        Some(mapper) => mapper.as_ref().and_then(|mapper| mapper.translate(address)),
+        None => None,
+    };
+    let fault = PageFault {
+        address,
+        error_code,
+        instruction_pointer: stack_frame.instruction_pointer,
+        region: Region::of(address),
+        translation,
+    };
+
+    //if the policy is being swapped right now, fall back to panicking
+    let policy = FAULT_POLICY.try_lock().map(|policy| *policy).unwrap_or(panic_policy);
+    match policy(&fault) {
+        FaultAction::Retry => None,
+        FaultAction::Panic => Some(fault),
+    }
+}
@@ -0,0 +1,322 @@
+/*Keyboard input.
+The keyboard interrupt handler turns scancodes into raw key events (which key, pressed or released) and queues
+them in a lock-free ring buffer, so it never waits for a lock and keys typed faster than they are read aren't
+lost (up to QUEUE_CAPACITY of them; beyond that they are dropped and counted, see overflows()).
+Readers take events out of the queue and decode them into KeyEvents (event.rs): the key, pressed or released,
+the modifiers and the character it types with the layout (layout.rs, switched with set_layout). The handler also
+keeps track of which keys are held down, for games (is_held).
+try_read_* return straight away; read_* block the current thread until a key comes in; tasks use KeyStream,
+which the handler wakes, so they can wait for keys without blocking the executor.
+Ref: https://os.phil-opp.com/hardware-interrupts/#keyboard-input and https://wiki.osdev.org/PS/2_Keyboard */
+
+pub mod event;
+pub mod layout;
+pub(crate) mod ps2;
+pub mod ring_buffer;
+
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use core::task::{Context, Poll};
+
+use alloc::string::String;
+use futures_util::stream::{Stream, StreamExt};
+use futures_util::task::AtomicWaker;
+use pc_keyboard::{DecodeState, KeyEvent as RawKeyEvent, ScancodeSet as _, ScancodeSet1, ScancodeSet2};
+use x86_64::instructions::interrupts;
+
+use crate::std::line_editor::LineEditor;
+use crate::sync::{IrqSafeMutex, WaitQueue};
+use crate::{print, println, task, time};
+use event::Decoder;
+use ring_buffer::RingBuffer;
+
+pub use event::{KeyCode, KeyEvent, KeyState, Modifiers};
+pub use layout::Layout;
+pub use pc_keyboard::HandleControl;
+
+/// The layout at boot, until set_layout() or choose_layout() changes it.
+pub const DEFAULT_LAYOUT: Layout = Layout::Us104;
+
+/// Which scancodes the keyboard handler decodes, see set_scancode_set().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeSet {
+    Set1,
+    Set2,
+}
+
+/// How many key events can wait to be read.
+pub const QUEUE_CAPACITY: usize = 128;
+
+//filled by the interrupt handler (the only producer), emptied under DECODER's lock (so one consumer at a time)
+static EVENTS: RingBuffer<RawKeyEvent, QUEUE_CAPACITY> = RingBuffer::new();
+
+//one bit per KeyCode, set while the key is down. Updated by the handler as keys go down and up,
+//whether or not anybody reads the events
+static HELD: [AtomicU64; 2] = [AtomicU64::new(0), AtomicU64::new(0)];
+const _: () = assert!((KeyCode::PowerOnTestOk as usize) < 128, "HELD has a bit for 128 key codes");
+
+//where the handler is in a multi-byte scancode sequence (e.g. 0xE0 0x48 for arrow up). Only the handler uses it
+static DECODE_STATE: AtomicU8 = AtomicU8::new(0);
+//0 for set 1 (what the PS/2 controller translates to by default), 1 for set 2
+static SCANCODE_SET: AtomicU8 = AtomicU8::new(0);
+
+//threads waiting in read_event()/read_key()
+static READERS: WaitQueue = WaitQueue::new();
+
+//the task waiting on the KeyStream, and whether there is one
+static STREAM_WAKER: AtomicWaker = AtomicWaker::new();
+static STREAM_TAKEN: AtomicBool = AtomicBool::new(false);
+//read_line calls take turns on the one KeyStream
+static LINE_READER: task::sync::Mutex<()> = task::sync::Mutex::new(());
+
+static DECODER: IrqSafeMutex<Decoder> = IrqSafeMutex::new(Decoder::new(DEFAULT_LAYOUT));
+
+fn decode_state_to_u8(state: DecodeState) -> u8 {
+    match state {
+        DecodeState::Start => 0,
+        DecodeState::Extended => 1,
+        DecodeState::Release => 2,
+        DecodeState::ExtendedRelease => 3,
+    }
+}
+
+fn decode_state_from_u8(state: u8) -> DecodeState {
+    match state {
+        1 => DecodeState::Extended,
+        2 => DecodeState::Release,
+        3 => DecodeState::ExtendedRelease,
+        _ => DecodeState::Start,
+    }
+}
+
+//pc_keyboard 0.5 calls the key next to Enter BackSlash in set 1 (HashTilde in set 2) and doesn't know the extra
+//ISO key next to the left Shift (0x56). Name both like set 2 does, see layout.rs
+fn advance_set1(state: &mut DecodeState, scancode: u8) -> Result<Option<RawKeyEvent>, pc_keyboard::Error> {
+    let key_state = if scancode & 0x80 != 0 { KeyState::Up } else { KeyState::Down };
+    match (*state, scancode & 0x7F) {
+        (DecodeState::Start, 0x2B) => Ok(Some(RawKeyEvent::new(KeyCode::HashTilde, key_state))),
+        (DecodeState::Start, 0x56) => Ok(Some(RawKeyEvent::new(KeyCode::BackSlash, key_state))),
+        _ => ScancodeSet1::advance_state(state, scancode),
+    }
+}
+
+/// Called by the keyboard interrupt handler with each byte read from the PS/2 data port.
+pub(crate) fn handle_scancode(scancode: u8) {
+    let mut state = decode_state_from_u8(DECODE_STATE.load(Ordering::Relaxed));
+    let event = match scancode_set() {
+        ScancodeSet::Set1 => advance_set1(&mut state, scancode),
+        ScancodeSet::Set2 => ScancodeSet2::advance_state(&mut state, scancode),
+    };
+    DECODE_STATE.store(decode_state_to_u8(state), Ordering::Relaxed);
+
+    //Err: a byte that isn't a key (e.g. the keyboard's self test result). Ok(None): the middle of a sequence
+    if let Ok(Some(event)) = event {
+        let (word, bit) = (event.code as usize / 64, 1u64 << (event.code as usize % 64));
+        match event.state {
+            KeyState::Down => HELD[word].fetch_or(bit, Ordering::Relaxed),
+            KeyState::Up => HELD[word].fetch_and(!bit, Ordering::Relaxed),
+        };
+        //the interrupt handler is the only producer, and doesn't interrupt itself.
+        //A full queue drops the event; EVENTS counts it
+        let _ = unsafe { EVENTS.push(event) };
+        READERS.wake_all();
+        STREAM_WAKER.wake();
+    }
+}
+
+/// Switches the layout keys are decoded with. Keys already waiting are decoded with the new one.
+pub fn set_layout(layout: Layout) {
+    DECODER.lock().layout = layout;
+}
+
+pub fn layout() -> Layout {
+    DECODER.lock().layout
+}
+
+/// What Ctrl + a letter types. HandleControl::Ignore (the default) types the letter, so programs have to look at
+/// KeyEvent::modifiers; HandleControl::MapLettersToUnicode types the control character instead (Ctrl+A is
+/// '\u{1}', Ctrl+C is '\u{3}', Ctrl+D is '\u{4}', ...). input_str and read_line understand both.
+pub fn set_handle_control(handle_ctrl: HandleControl) {
+    DECODER.lock().handle_ctrl = handle_ctrl;
+}
+
+pub fn handle_control() -> HandleControl {
+    DECODER.lock().handle_ctrl
+}
+
+/// Switches the scancode set, by turning the PS/2 controller's translation to set 1 on (Set1) or off (Set2).
+/// Keys typed but not handled yet are lost. Returns false, and keeps the current set, if the controller didn't
+/// answer.
+pub fn set_scancode_set(set: ScancodeSet) -> bool {
+    interrupts::without_interrupts(|| {
+        if !unsafe { ps2::set_translation(set == ScancodeSet::Set1) } {
+            return false;
+        }
+        SCANCODE_SET.store(set as u8, Ordering::Relaxed);
+        DECODE_STATE.store(decode_state_to_u8(DecodeState::Start), Ordering::Relaxed);
+        true
+    })
+}
+
+pub fn scancode_set() -> ScancodeSet {
+    match SCANCODE_SET.load(Ordering::Relaxed) {
+        0 => ScancodeSet::Set1,
+        _ => ScancodeSet::Set2,
+    }
+}
+
+/// Lets the user pick the layout at boot: F1 to F5 (in Layout::ALL order) within `seconds` choose one, anything
+/// else keeps the current one. Function keys are in the same place on every layout, unlike letters and digits.
+pub fn choose_layout(seconds: u64) {
+    print!("Keyboard layout: {}. Press", layout());
+    for (number, layout) in Layout::ALL.iter().enumerate() {
+        print!(" F{} for {},", number + 1, layout);
+    }
+    println!(" within {} seconds to change it", seconds);
+
+    let deadline = time::ticks().saturating_add(time::duration_to_ticks(core::time::Duration::from_secs(seconds)));
+    let key = loop {
+        match try_read_key() {
+            Some(key) => break Some(key),
+            None if time::ticks() >= deadline => break None,
+            None => time::sleep_ms(10),
+        }
+    };
+    let chosen = key.and_then(|key| match key.code {
+        KeyCode::F1 => Some(0),
+        KeyCode::F2 => Some(1),
+        KeyCode::F3 => Some(2),
+        KeyCode::F4 => Some(3),
+        KeyCode::F5 => Some(4),
+        _ => None,
+    });
+    if let Some(index) = chosen {
+        set_layout(Layout::ALL[index]);
+    }
+    println!("Using the {} keyboard layout", layout());
+}
+
+/// Whether `code` is held down right now. Games can call this every frame instead of reading events.
+pub fn is_held(code: KeyCode) -> bool {
+    let (word, bit) = (code as usize / 64, 1u64 << (code as usize % 64));
+    HELD[word].load(Ordering::Relaxed) & bit != 0
+}
+
+/// Key events that were dropped because nobody read the queue in time.
+pub fn overflows() -> u64 {
+    EVENTS.overflows()
+}
+
+/// Key events waiting to be read.
+pub fn pending() -> usize {
+    EVENTS.len()
+}
+
+/// The next key event, press or release, if one is waiting.
+pub fn try_read_event() -> Option<KeyEvent> {
+    let mut decoder = DECODER.lock();
+    unsafe { EVENTS.pop() }.map(|event| decoder.decode(event))
+}
+
+/// The next key press, if one is waiting. Releases are used up along the way (the modifiers are still tracked).
+pub fn try_read_key() -> Option<KeyEvent> {
+    loop {
+        let event = try_read_event()?;
+        if event.is_press() {
+            return Some(event);
+        }
+    }
+}
+
+/// Blocks the current thread until a key event comes in. Tasks shouldn't call this: it blocks the executor.
+pub fn read_event() -> KeyEvent {
+    loop {
+        if let Some(event) = try_read_event() {
+            return event;
+        }
+        READERS.wait_until(|| !EVENTS.is_empty());
+    }
+}
+
+/// Blocks the current thread until a key is pressed.
+pub fn read_key() -> KeyEvent {
+    loop {
+        if let Some(key) = try_read_key() {
+            return key;
+        }
+        READERS.wait_until(|| !EVENTS.is_empty());
+    }
+}
+
+/// The keys pressed, for tasks: `while let Some(key) = keys.next().await` (with futures_util::StreamExt).
+/// There can only be one at a time, since there is only one waker to wake: new() gives None while another one
+/// exists, and dropping it makes room for the next. It never ends.
+pub struct KeyStream {
+    releases: bool,
+}
+
+impl KeyStream {
+    /// Key presses, like [read_key]. None if there is a KeyStream already.
+    pub fn new() -> Option<KeyStream> {
+        if STREAM_TAKEN.swap(true, Ordering::AcqRel) {
+            return None; //not making one here: dropping it would free the slot of the one that exists
+        }
+        Some(KeyStream { releases: false })
+    }
+
+    /// Presses and releases, like [read_event]. None if there is a KeyStream already.
+    pub fn with_releases() -> Option<KeyStream> {
+        let mut stream = KeyStream::new()?;
+        stream.releases = true;
+        Some(stream)
+    }
+
+    fn try_next(&self) -> Option<KeyEvent> {
+        match self.releases {
+            true => try_read_event(),
+            false => try_read_key(),
+        }
+    }
+}
+
+impl Stream for KeyStream {
+    type Item = KeyEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<KeyEvent>> {
+        if let Some(key) = self.try_next() {
+            return Poll::Ready(Some(key));
+        }
+        //register before looking again, so a key that comes in between wakes us
+        STREAM_WAKER.register(cx.waker());
+        match self.try_next() {
+            Some(key) => {
+                STREAM_WAKER.take();
+                Poll::Ready(Some(key))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for KeyStream {
+    fn drop(&mut self) {
+        STREAM_WAKER.take();
+        STREAM_TAKEN.store(false, Ordering::Release);
+    }
+}
+
+/// The async version of std::input_str, for tasks: while it waits for keys, the executor runs other tasks.
+/// It edits the line the same way (std/line_editor.rs) and shares its history.
+/// Only one read_line reads keys at a time; others wait their turn. None (like a cancelled line) if a task
+/// holds a KeyStream of its own.
+pub async fn read_line() -> Option<String> {
+    let _turn = LINE_READER.lock().await;
+    let mut keys = KeyStream::new()?;
+    let mut editor = LineEditor::new();
+    while let Some(key) = keys.next().await {
+        if let Poll::Ready(line) = editor.handle(key) {
+            return line;
+        }
+    }
+    None //KeyStream never ends
+}
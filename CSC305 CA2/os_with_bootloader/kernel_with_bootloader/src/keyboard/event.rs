@@ -0,0 +1,127 @@
+//Key events as programs see them: which key, pressed or released, the modifier keys at the time, and the
+//character it types (if any). Text input looks at `unicode`; games look at `code` and `state`, or ask
+//keyboard::is_held() each frame.
+//pc_keyboard's Keyboard keeps its modifiers to itself, so the Decoder below tracks them and asks the layout
+//for the character.
+
+use pc_keyboard::{DecodedKey, HandleControl};
+
+use super::layout::Layout;
+
+pub use pc_keyboard::{KeyCode, KeyState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// Including this event: Shift's own press already has `shift()` set.
+    pub modifiers: Modifiers,
+    /// What the key types with these modifiers. Only set for presses.
+    pub unicode: Option<char>,
+}
+
+impl KeyEvent {
+    pub fn is_press(&self) -> bool {
+        self.state == KeyState::Down
+    }
+
+    pub fn is_release(&self) -> bool {
+        self.state == KeyState::Up
+    }
+}
+
+/// Which modifier keys are held, and whether Caps Lock and Num Lock are on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub lshift: bool,
+    pub rshift: bool,
+    pub lctrl: bool,
+    pub rctrl: bool,
+    pub lalt: bool,
+    pub alt_gr: bool, //the right Alt key
+    pub capslock: bool,
+    pub numlock: bool,
+}
+
+impl Modifiers {
+    //Num Lock starts on, like on a PC after boot
+    pub const fn new() -> Modifiers {
+        Modifiers {
+            lshift: false,
+            rshift: false,
+            lctrl: false,
+            rctrl: false,
+            lalt: false,
+            alt_gr: false,
+            capslock: false,
+            numlock: true,
+        }
+    }
+
+    pub fn shift(&self) -> bool {
+        self.lshift || self.rshift
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.lctrl || self.rctrl
+    }
+
+    pub fn alt(&self) -> bool {
+        self.lalt || self.alt_gr
+    }
+
+    fn to_pc_keyboard(self) -> pc_keyboard::Modifiers {
+        pc_keyboard::Modifiers {
+            lshift: self.lshift,
+            rshift: self.rshift,
+            lctrl: self.lctrl,
+            rctrl: self.rctrl,
+            numlock: self.numlock,
+            capslock: self.capslock,
+            alt_gr: self.alt_gr,
+        }
+    }
+}
+
+impl Default for Modifiers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turns raw events (key and up/down, from the interrupt handler) into [KeyEvent]s.
+pub(super) struct Decoder {
+    modifiers: Modifiers,
+    pub(super) layout: Layout,
+    pub(super) handle_ctrl: HandleControl,
+}
+
+impl Decoder {
+    pub(super) const fn new(layout: Layout) -> Decoder {
+        Decoder { modifiers: Modifiers::new(), layout, handle_ctrl: HandleControl::Ignore }
+    }
+
+    pub(super) fn decode(&mut self, raw: pc_keyboard::KeyEvent) -> KeyEvent {
+        let down = raw.state == KeyState::Down;
+        let modifiers = &mut self.modifiers;
+        match raw.code {
+            KeyCode::ShiftLeft => modifiers.lshift = down,
+            KeyCode::ShiftRight => modifiers.rshift = down,
+            KeyCode::ControlLeft => modifiers.lctrl = down,
+            KeyCode::ControlRight => modifiers.rctrl = down,
+            KeyCode::AltLeft => modifiers.lalt = down,
+            KeyCode::AltRight => modifiers.alt_gr = down,
+            KeyCode::CapsLock if down => modifiers.capslock = !modifiers.capslock,
+            KeyCode::NumpadLock if down => modifiers.numlock = !modifiers.numlock,
+            _ => {}
+        }
+        let unicode = match down {
+            true => match self.layout.map_keycode(raw.code, &self.modifiers.to_pc_keyboard(), self.handle_ctrl) {
+                DecodedKey::Unicode(character) => Some(character),
+                DecodedKey::RawKey(_) => None,
+            },
+            false => None,
+        };
+        KeyEvent { code: raw.code, state: raw.state, modifiers: self.modifiers, unicode }
+    }
+}
@@ -0,0 +1,71 @@
+//Keyboard layouts: which character each key types. The key codes are the same whatever is printed on the keys;
+//the layout maps them (with Shift, AltGr, Caps Lock, ...) to characters.
+//ISO keyboards (UK, German, French) have one more key than US ones, next to the left Shift, and a different key
+//next to Enter. We name those two like scancode set 2 does: KeyCode::HashTilde is the one next to Enter and
+//KeyCode::BackSlash the one next to the left Shift (handle_scancode renames set 1's codes to match).
+//US keyboards' backslash key sits next to Enter too, so the US layouts treat HashTilde as BackSlash.
+
+mod de105;
+
+use core::fmt;
+
+use pc_keyboard::{layouts, DecodedKey, HandleControl, KeyCode, KeyboardLayout, Modifiers};
+
+pub use de105::De105Key;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Us104,
+    Uk105,
+    De105, //QWERTZ
+    Azerty,
+    Dvorak,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 5] = [Layout::Us104, Layout::Uk105, Layout::De105, Layout::Azerty, Layout::Dvorak];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Us104 => "us",
+            Layout::Uk105 => "uk",
+            Layout::De105 => "de",
+            Layout::Azerty => "azerty",
+            Layout::Dvorak => "dvorak",
+        }
+    }
+
+    /// The layout called `name` (see [Layout::name]), ignoring case.
+    pub fn from_name(name: &str) -> Option<Layout> {
+        Layout::ALL.into_iter().find(|layout| layout.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub(super) fn map_keycode(self, keycode: KeyCode, modifiers: &Modifiers, handle_ctrl: HandleControl) -> DecodedKey {
+        match self {
+            Layout::Us104 => layouts::Us104Key::map_keycode(us_key(keycode), modifiers, handle_ctrl),
+            Layout::Uk105 => layouts::Uk105Key::map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::De105 => De105Key::map_keycode(keycode, modifiers, handle_ctrl),
+            //pc_keyboard's Azerty has the two ISO keys the other way round
+            Layout::Azerty => match keycode {
+                KeyCode::HashTilde => DecodedKey::Unicode(if modifiers.is_shifted() { 'µ' } else { '*' }),
+                KeyCode::BackSlash => DecodedKey::Unicode(if modifiers.is_shifted() { '>' } else { '<' }),
+                other => layouts::Azerty::map_keycode(other, modifiers, handle_ctrl),
+            },
+            Layout::Dvorak => layouts::Dvorak104Key::map_keycode(us_key(keycode), modifiers, handle_ctrl),
+        }
+    }
+}
+
+//the key next to Enter is the backslash key on US keyboards
+fn us_key(keycode: KeyCode) -> KeyCode {
+    match keycode {
+        KeyCode::HashTilde => KeyCode::BackSlash,
+        other => other,
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -0,0 +1,60 @@
+//The German 105-key (QWERTZ) layout, which pc_keyboard 0.5 doesn't have.
+//Like pc_keyboard's Uk105Key it only handles the keys that differ and hands the rest to Us104Key.
+//Dead keys (^, ´, `) simply type themselves.
+//Ref: https://kbdlayout.info/kbdgr
+
+use pc_keyboard::{layouts::Us104Key, DecodedKey, HandleControl, KeyCode, KeyboardLayout, Modifiers};
+
+pub struct De105Key;
+
+//`normal`, with Shift, with AltGr (None: like normal)
+fn pick(modifiers: &Modifiers, normal: char, shifted: char, alt_gr: Option<char>) -> DecodedKey {
+    let character = match alt_gr {
+        Some(character) if modifiers.alt_gr => character,
+        _ if modifiers.is_shifted() => shifted,
+        _ => normal,
+    };
+    DecodedKey::Unicode(character)
+}
+
+//umlauts follow Caps Lock like letters do
+fn letter(modifiers: &Modifiers, lower: char, upper: char) -> DecodedKey {
+    DecodedKey::Unicode(if modifiers.is_caps() { upper } else { lower })
+}
+
+impl KeyboardLayout for De105Key {
+    fn map_keycode(keycode: KeyCode, modifiers: &Modifiers, handle_ctrl: HandleControl) -> DecodedKey {
+        match keycode {
+            KeyCode::BackTick => pick(modifiers, '^', '°', None),
+            KeyCode::Key1 => pick(modifiers, '1', '!', None),
+            KeyCode::Key2 => pick(modifiers, '2', '"', Some('²')),
+            KeyCode::Key3 => pick(modifiers, '3', '§', Some('³')),
+            KeyCode::Key4 => pick(modifiers, '4', '$', None),
+            KeyCode::Key5 => pick(modifiers, '5', '%', None),
+            KeyCode::Key6 => pick(modifiers, '6', '&', None),
+            KeyCode::Key7 => pick(modifiers, '7', '/', Some('{')),
+            KeyCode::Key8 => pick(modifiers, '8', '(', Some('[')),
+            KeyCode::Key9 => pick(modifiers, '9', ')', Some(']')),
+            KeyCode::Key0 => pick(modifiers, '0', '=', Some('}')),
+            KeyCode::Minus => pick(modifiers, 'ß', '?', Some('\\')),
+            KeyCode::Equals => pick(modifiers, '´', '`', None),
+            KeyCode::BracketSquareLeft => letter(modifiers, 'ü', 'Ü'),
+            KeyCode::BracketSquareRight => pick(modifiers, '+', '*', Some('~')),
+            KeyCode::SemiColon => letter(modifiers, 'ö', 'Ö'),
+            KeyCode::Quote => letter(modifiers, 'ä', 'Ä'),
+            KeyCode::HashTilde => pick(modifiers, '#', '\'', None), //next to Enter
+            KeyCode::BackSlash => pick(modifiers, '<', '>', Some('|')), //next to the left Shift
+            KeyCode::Comma => pick(modifiers, ',', ';', None),
+            KeyCode::Fullstop => pick(modifiers, '.', ':', None),
+            KeyCode::Slash => pick(modifiers, '-', '_', None),
+            KeyCode::Q if modifiers.alt_gr => DecodedKey::Unicode('@'),
+            KeyCode::E if modifiers.alt_gr => DecodedKey::Unicode('€'),
+            KeyCode::M if modifiers.alt_gr => DecodedKey::Unicode('µ'),
+            //QWERTZ: Y and Z swap places (Ctrl+Z included)
+            KeyCode::Y => Us104Key::map_keycode(KeyCode::Z, modifiers, handle_ctrl),
+            KeyCode::Z => Us104Key::map_keycode(KeyCode::Y, modifiers, handle_ctrl),
+            KeyCode::NumpadPeriod if modifiers.numlock => DecodedKey::Unicode(','),
+            other => Us104Key::map_keycode(other, modifiers, handle_ctrl),
+        }
+    }
+}
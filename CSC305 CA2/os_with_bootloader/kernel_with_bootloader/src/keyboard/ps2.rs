@@ -0,0 +1,65 @@
+//The PS/2 controller (the "8042") sits between the keyboard and the CPU. Keyboards send scancode set 2, but the
+//controller translates it into the old set 1 for the BIOS (bit 6 of its configuration byte), and QEMU leaves that on.
+//Turning translation off hands us the keyboard's own set 2 codes.
+//Ref: https://wiki.osdev.org/%228042%22_PS/2_Controller
+
+use x86_64::instructions::port::Port;
+
+const DATA_PORT: u16 = 0x60;
+const COMMAND_PORT: u16 = 0x64; //the status register when read
+
+//status register bits
+const OUTPUT_FULL: u8 = 1 << 0; //a byte is waiting in DATA_PORT
+const INPUT_FULL: u8 = 1 << 1; //the controller hasn't taken our last byte yet
+
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const TRANSLATION: u8 = 1 << 6;
+
+//status polls before giving up, e.g. on a machine without a PS/2 controller
+const TIMEOUT: u32 = 100_000;
+
+/// Whether a byte is waiting to be read from the data port.
+pub(crate) fn has_data() -> bool {
+    let mut status: Port<u8> = Port::new(COMMAND_PORT);
+    let status = unsafe { status.read() };
+    status & OUTPUT_FULL != 0
+}
+
+fn wait_until(mut ready: impl FnMut(u8) -> bool) -> bool {
+    let mut status: Port<u8> = Port::new(COMMAND_PORT);
+    (0..TIMEOUT).any(|_| ready(unsafe { status.read() }))
+}
+
+/// Turns the controller's set 2 to set 1 translation on or off. Returns false if the controller didn't answer.
+///
+/// # Safety
+/// Interrupts must be disabled, or the keyboard handler could take the controller's reply for a scancode.
+/// Keys waiting in the controller are thrown away.
+pub(super) unsafe fn set_translation(on: bool) -> bool {
+    let mut data: Port<u8> = Port::new(DATA_PORT);
+    let mut command: Port<u8> = Port::new(COMMAND_PORT);
+    unsafe {
+        //throw away waiting bytes, so the next one is the reply to READ_CONFIG
+        for _ in (0..TIMEOUT).take_while(|_| has_data()) {
+            data.read();
+        }
+        if !wait_until(|status| status & INPUT_FULL == 0) {
+            return false;
+        }
+        command.write(READ_CONFIG);
+        if !wait_until(|status| status & OUTPUT_FULL != 0) {
+            return false;
+        }
+        let config = match on {
+            true => data.read() | TRANSLATION,
+            false => data.read() & !TRANSLATION,
+        };
+        command.write(WRITE_CONFIG);
+        if !wait_until(|status| status & INPUT_FULL == 0) {
+            return false;
+        }
+        data.write(config);
+    }
+    true
+}
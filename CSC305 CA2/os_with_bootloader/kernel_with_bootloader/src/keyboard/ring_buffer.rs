@@ -0,0 +1,95 @@
+//A fixed-capacity ring buffer for exactly one producer and one consumer, without locks.
+//The producer only moves `tail` and the consumer only moves `head`, so each side just needs to see the other's
+//index with Acquire/Release ordering. Both count up forever (wrapping); `index % N` is the slot.
+//When it is full, push() gives the value back and counts an overflow: the producer is usually an interrupt
+//handler, which can't wait for room.
+//Ref: https://www.snellman.net/blog/archive/2016-12-13-ring-buffers/
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+pub struct RingBuffer<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    head: AtomicUsize, //next slot to read
+    tail: AtomicUsize, //next slot to write
+    overflows: AtomicU64,
+}
+
+//a value is only touched by the producer before push() and by the consumer after pop()
+unsafe impl<T: Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+impl<T, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> RingBuffer<T, N> {
+        RingBuffer {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            overflows: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds `value` at the back. If the buffer is full, counts an overflow and returns `value`.
+    ///
+    /// # Safety
+    /// Only one producer at a time: push() must not run concurrently with itself (an interrupt handler that
+    /// can't interrupt itself is fine).
+    pub unsafe fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire); //the consumer is done with the slots before head
+        if tail.wrapping_sub(head) == N {
+            self.overflows.fetch_add(1, Ordering::Relaxed);
+            return Err(value);
+        }
+        unsafe { (*self.slots[tail % N].get()).write(value) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release); //publishes the value
+        Ok(())
+    }
+
+    /// Takes the value at the front.
+    ///
+    /// # Safety
+    /// Only one consumer at a time: pop() must not run concurrently with itself.
+    pub unsafe fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let value = unsafe { (*self.slots[head % N].get()).assume_init_read() };
+        self.head.store(head.wrapping_add(1), Ordering::Release); //hands the slot back to the producer
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        //head first: tail only moves forward, so whatever happens between the loads, tail >= head
+        let head = self.head.load(Ordering::Acquire);
+        self.tail.load(Ordering::Acquire).wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// How many values push() had to turn away because the buffer was full.
+    pub fn overflows(&self) -> u64 {
+        self.overflows.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, const N: usize> Drop for RingBuffer<T, N> {
+    fn drop(&mut self) {
+        //`&mut self`: no producer or consumer left
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
@@ -3,11 +3,22 @@
 #![feature(allow_internal_unstable)] //demanded by #[allow_internal_unstable(print_internals, format_args_nl)] in my std.rs
 //below is for x86 interrupts
 #![feature(abi_x86_interrupt)]
-mod interrupts;
+#![feature(alloc_error_handler)] //lets allocator.rs report out-of-memory instead of an opaque abort
+pub mod allocator;
+pub mod backtrace;
+mod gdt;
+pub mod interrupts;
+pub mod keyboard;
+pub mod memory;
+#[cfg(feature = "selftest")]
+mod selftest;
 mod smart_pointer_examples;
-pub(crate) mod std;
+pub mod std; //public: the #[macro_export] macros in it expand to paths into std::input
+pub mod sync;
 pub mod task;
 mod task_example;
+pub mod thread;
+pub mod time;
 mod writer;
 
 use alloc::{borrow::ToOwned, sync::Arc};
@@ -15,17 +26,11 @@ use alloc::{borrow::ToOwned, sync::Arc};
 use writer::FrameBufferWriter;
 use x86_64::instructions::hlt;
 
-//let's get heap memory allocation going
+//let's get heap memory allocation going. The global allocator lives in allocator.rs
 extern crate alloc;
-use good_memory_allocator::SpinLockedAllocator;
 
-#[global_allocator]
-static ALLOCATOR: SpinLockedAllocator = SpinLockedAllocator::empty();
-
-use bootloader_api::{
-    config::Mapping,
-    info::{MemoryRegion, MemoryRegionKind},
-};
+use bootloader_api::config::Mapping;
+use x86_64::VirtAddr;
 
 
 //Use the entry_point macro to register the entry point function: bootloader_api::entry_point!(kernel_main)
@@ -39,15 +44,16 @@ pub static BOOTLOADER_CONFIG: bootloader_api::BootloaderConfig = {
 bootloader_api::entry_point!(my_entry_point, config = &BOOTLOADER_CONFIG);
 
 use lazy_static::lazy_static;
-use spin::Mutex;
+use crate::sync::IrqSafeMutex;
 
 use crate::{std::input_str, task::{simple_executor::SimpleExecutor, Task}};
 
 //use lazy static to allow declaration of static without initializing with a constant value
-//Mutex from spin is used for control of threads access.
+//IrqSafeMutex is used for control of threads access. Panic and exception handlers print through it too, so the
+//writer is locked with interrupts disabled: a handler can't come in while print! holds it and deadlock
 lazy_static! {
-    pub(crate) static ref FRAME_BUFFER_WRITER: Mutex<FrameBufferWriter> =
-        Mutex::new(FrameBufferWriter::empty());
+    pub(crate) static ref FRAME_BUFFER_WRITER: IrqSafeMutex<FrameBufferWriter> =
+        IrqSafeMutex::new(FrameBufferWriter::empty());
 }
 
 fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
@@ -75,30 +81,38 @@ fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
         4.0 / 2.0
     );*/
 
-    //let's initialize our global memory allocator
-    let last_memory_region = boot_info.memory_regions.last().unwrap();
-
-    //get the first bootload memory
-    let mut boot_loader_memory_region = MemoryRegion::empty();
-
-    for memory_region in boot_info.memory_regions.iter() {
-        match memory_region.kind {
-            MemoryRegionKind::Bootloader => {
-                boot_loader_memory_region = *memory_region;
-                break;
-            }
-            _ => continue,
-        }
-    }
+    //load our GDT/TSS and the IDT first, so exceptions during the rest of boot (including a kernel stack
+    //overflow, which switches to a separate IST stack) print a panic message instead of rebooting QEMU
+    gdt::init();
+    interrupts::init_idt();
 
+    //let's initialize our physical frame allocator and page table mapper. The frame allocator only hands out
+    //memory regions the bootloader reported as Usable, so ACPI, reserved and framebuffer memory are never given out.
+    //The mapper edits the active page tables through the physical memory mapping set up by the bootloader.
     let physical_memory_offset = boot_info.physical_memory_offset.into_option().unwrap();
 
-    let heap_start = boot_loader_memory_region.end + 0x1 + physical_memory_offset;
-    let heap_size = last_memory_region.end - (boot_loader_memory_region.end + 0x1);
-
     unsafe {
-        ALLOCATOR.init(heap_start as usize, heap_size as usize);
+        memory::init(&boot_info.memory_regions, VirtAddr::new(physical_memory_offset));
     }
+    //remember where the stack, kernel image etc. are, so the page fault handler can tell what a bad address hit
+    memory::layout::init(
+        &boot_info.memory_regions,
+        VirtAddr::new(physical_memory_offset),
+        boot_info.kernel_image_offset,
+        boot_info.kernel_len,
+    );
+    //the ramdisk holds the kernel's symbol table (made by os_with_bootloader/build.rs), used to name functions in backtraces
+    unsafe {
+        backtrace::init(boot_info.ramdisk_addr.into_option(), boot_info.ramdisk_len);
+    }
+
+    //let's initialize our global memory allocator. The heap has its own virtual range which starts small
+    //and is mapped further on demand whenever an allocation does not fit.
+    allocator::init().expect("failed to map the kernel heap");
+
+    //pick how kernel threads share the CPU (thread/policy.rs). Try policy::StaticPriority::new() or
+    //policy::FairShare::new() instead, and thread::print_ps() to see what each thread got
+    thread::init(thread::policy::RoundRobin::new());
 
     //Let's do a quick test of our heap, using smart pointers
     use alloc::boxed::Box;
@@ -110,40 +124,48 @@ fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
     let y = 33;
     println!("\nValue in stack is {}", &y);
 
-    //Let's see some more smart pointer examples
+    //Let's see some more smart pointer examples.
+    //The heap mark lets us check afterwards that they gave back everything they allocated (no Rc cycle leaks)
     use smart_pointer_examples::*;
+    let heap_mark = allocator::heap_mark();
     box_vs_rc();
     
     let root = create_tree();
     add_child(&root);
     print_tree(root);
+    println!("\nHeap after smart pointer examples: {}", heap_mark.compare());
+    println!("{}", allocator::heap_stats());
+
+    //Compare heap allocators: build with e.g. `--features bump-allocator` to benchmark another backend.
+    //Uncomment for experience
+    //allocator::bench::run();
 
     /*
     //let's see some cooperative multitasking examples. Uncomment for experience
     //1. Use self-built executor
 
     let mut executor = SimpleExecutor::new();
-    executor.spawn(Task::new(run_future()));
+    executor.spawn(Task::new(async { println!("RESULT: {}", run_future().await) }));
     //run task_examples
     use task_example::*;
     executor.spawn(Task::new(example_task()));
     executor.run();
    
-    //Exercise: write a macro named thread_spawn! for the above
-    //that will receive only the task function to spawn
+    //thread_spawn! does the above with only the task function, on the kernel-wide executor (see task/global.rs):
+    //thread_spawn!(run_future(), example_task());
     
     //Sharing data    
-    let data = Arc::new(Mutex::new(task_example::SharedData { value: 30 }));
+    let data = Arc::new(spin::Mutex::new(task_example::SharedData { value: 30 }));
     executor.spawn(Task::new(run_modify_data(data.clone())));
     executor.run();
     executor.spawn(Task::new(run_modify_data(data.clone())));
     executor.run();
     */
-    /* join! below only available in std mod in futures-rs
-    let thread1 = executor.spawn(Task::new(run_modify_data(data.clone())));
-    let thread2 = executor.spawn(Task::new(run_modify_data(data.clone())));
-
-    futures::join!(thread1, thread2);
+    /* futures::join! is only available with std, so we have our own join! (see task/join.rs)
+    let thread1 = run_modify_data(data.clone());
+    let thread2 = run_modify_data(data.clone());
+    executor.spawn(Task::new(async { join!(thread1, thread2); }));
+    executor.run();
     */
     
     //2. Illustrate a ready-made executor
@@ -153,7 +175,87 @@ fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
     //For premptive multitasking, we use interrupts
     interrupts::init();
 
+    //Check the task sync primitives (channels, async Mutex, ...) on the executor. Build with `--features selftest`
+    //to run the checks
+    #[cfg(feature = "selftest")]
+    task::sync::selftest::run();
+    //and the blocking ones (sync.rs), with kernel threads that need the timer to wake up from sleep
+    #[cfg(feature = "selftest")]
+    sync::selftest::run();
+
+    //The timer now ticks every millisecond. Uncomment to see the uptime clock and sleep_ms at work
+    /*
+    println!("Uptime: {:?}, timer at {} Hz", time::uptime(), time::frequency());
+    time::sleep_ms(1000);
+    println!("Uptime after sleeping for a second: {:?} ({} ticks)", time::uptime(), time::ticks());
+    */
+
     //Let's experience getting string from keyboard and saving into a variable for use
+    //Tasks can sleep now that the timer interrupt is on. Uncomment for experience.
+    //The global executor only polls tasks that were woken and halts the CPU in between, unlike SimpleExecutor.
+    //block_on runs it until the given future completes
+    /*
+    thread_spawn!(task_example::example_task());
+    task::block_on(task_example::sleep_example());
+    */
+
+    //Sharing data between tasks that hold the lock across an .await needs an async Mutex. Uncomment for experience
+    /*
+    let data = Arc::new(task::sync::Mutex::new(task_example::SharedData { value: 30 }));
+    let handles = thread_spawn!(task_example::run_modify_data_async(data.clone()), task_example::run_modify_data_async(data.clone()));
+    task::block_on(async { join!(handles.0, handles.1) });
+    */
+
+    //spawn returns a JoinHandle to wait for (or abort) a task and get its output. Uncomment for experience
+    /*
+    let result = thread_spawn!(task_example::run_future());
+    let sleepers = [300, 100, 200].map(|ms| task::spawn(task_example::sleep_for(ms))).into();
+    let forever = task::spawn(task::sleep(core::time::Duration::MAX));
+    task::block_on(task_example::join_example(result, sleepers, forever));
+    */
+
+    //Reading a line in a task doesn't hold up the other tasks: the example task keeps printing while you type.
+    //Uncomment for experience
+    /*
+    thread_spawn!(task_example::example_task());
+    print!("Enter string: ");
+    println!("\nString entered is '{:?}'", task::block_on(keyboard::read_line()));
+    */
+
+    //Key events tell presses from releases and carry the modifiers, e.g. for games. Uncomment to see them;
+    //Escape stops
+    /*
+    loop {
+        let event = keyboard::read_event();
+        println!("{:?} {:?} ctrl: {} shift: {} char: {:?}", event.code, event.state, event.modifiers.ctrl(),
+            event.modifiers.shift(), event.unicode);
+        if event.code == keyboard::KeyCode::Escape {
+            break;
+        }
+        if keyboard::is_held(keyboard::KeyCode::ArrowUp) && keyboard::is_held(keyboard::KeyCode::ArrowRight) {
+            println!("Going up and right");
+        }
+    }
+    */
+
+    //input! parses what is typed into the type you ask for, and asks again until it can. Uncomment for experience
+    /*
+    let age: u8 = input!("Age: ");
+    let height: f32 = input!("Height in metres: ", default = 1.75);
+    let month: u32 = input!("Month of birth: ", range = 1..=12);
+    let layout = keyboard::Layout::ALL[choose!("Which keyboard do you have?", &keyboard::Layout::ALL)];
+    if confirm!("Switch to the {} layout?", layout) {
+        keyboard::set_layout(layout);
+    }
+    println!("{} years old, {} m tall, born in month {}", age, height, month);
+    */
+
+    //The keyboard starts with keyboard::DEFAULT_LAYOUT; keyboard::set_layout() switches it at any time.
+    //Uncomment to pick it with F1-F5 at boot instead (3 seconds to answer).
+    //keyboard::set_handle_control(keyboard::HandleControl::MapLettersToUnicode) lets Ctrl+C and Ctrl+D cancel
+    //input_str, and keyboard::set_scancode_set(keyboard::ScancodeSet::Set2) reads the keyboard's own scancodes
+    //keyboard::choose_layout(3);
+
     print!("Enter string: ");
     let input = match input_str() {
         Some(value) => value,
@@ -180,21 +282,66 @@ fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
 
     //println!("Did not crash after breakpoint exception");
 
-    // Below can trigger a page fault. Just for test
+    // Below overflows the kernel stack. Thanks to the double fault IST stack we get a panic instead of a reboot
+    /*
+    #[allow(unconditional_recursion)]
+    fn stack_overflow() {
+        stack_overflow();
+        core::hint::black_box(0); //stop the compiler from turning the recursion into a loop
+    }
+    stack_overflow();
+    */
+
+    // Below can trigger a page fault. Just for test. The page fault handler reports the address and panics
     /* 
     unsafe {
         *(0xdeadbeef as *mut u8) = 42; //invalid memory address
     };*/
+
+    // Below recovers from the page fault instead: a fault policy maps the missing page and the write is retried
+    /*
+    use interrupts::page_fault::{self, FaultAction, PageFault};
+    fn demand_paging(fault: &PageFault) -> FaultAction {
+        if fault.address.as_u64() >> 12 == 0xdeadbeef >> 12 {
+            page_fault::map_faulting_page(fault)
+        } else {
+            FaultAction::Panic
+        }
+    }
+    page_fault::set_fault_policy(demand_paging);
+    unsafe {
+        *(0xdeadbeef as *mut u8) = 42;
+        println!("Read back {} after the page fault", *(0xdeadbeef as *const u8));
+    };*/
     
 
-    loop {
-        hlt(); //stop x86_64 from being unnecessarily busy while looping
+    //Preemptive threads: the timer interrupt switches between them every 10 ms, whether they cooperate or not.
+    //Uncomment for experience
+    /*
+    let workers: alloc::vec::Vec<_> = (1..=3)
+        .map(|n| thread::spawn(move || {
+            for i in 0..3 {
+                println!("thread {} at {:?}: {} / 2.0 = {}", n, time::uptime(), i * n, (i * n) as f64 / 2.0);
+                thread::sleep_ms(100 * n as u64);
+            }
+            n * 10
+        }))
+        .collect();
+    for worker in workers {
+        println!("thread returned {}", worker.join());
     }
+    thread::print_ps();
+    */
+
+    //Boot is done. From here on the kernel is whatever the spawned tasks do; the executor halts the CPU
+    //whenever none of them is ready, so x86_64 isn't unnecessarily busy
+    task::run()
 }
 
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
     println!("{}", _info);
+    println!("{}", backtrace::Backtrace::capture());
     loop {
         hlt();
     }
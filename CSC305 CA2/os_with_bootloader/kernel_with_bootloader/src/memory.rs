@@ -0,0 +1,144 @@
+pub mod frame_allocator;
+pub mod layout;
+pub mod paging;
+
+use bootloader_api::info::MemoryRegion;
+use spin::Mutex;
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+use frame_allocator::BitmapFrameAllocator;
+use paging::{KernelMapper, MapError, Translation};
+
+//The one physical frame allocator of the kernel. It is None until init_frame_allocator() is called
+//from my_entry_point. The heap and page tables get their frames from here.
+pub static FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+
+//The kernel's view of the active page tables. None until init_mapper() is called.
+//Lock order: MAPPER first, then FRAME_ALLOCATOR (the mapper needs frames for new page tables).
+pub static MAPPER: Mutex<Option<KernelMapper>> = Mutex::new(None);
+
+/// Sets up the frame allocator and the page table mapper.
+///
+/// # Safety
+/// The complete physical memory must be mapped at `physical_memory_offset` and the usable
+/// regions of `memory_regions` must be unused. Must be called exactly once.
+pub unsafe fn init(memory_regions: &[MemoryRegion], physical_memory_offset: VirtAddr) {
+    init_frame_allocator(memory_regions, physical_memory_offset);
+    init_mapper(physical_memory_offset);
+}
+
+/// Builds the global frame allocator from the bootloader memory map.
+///
+/// # Safety
+/// See [BitmapFrameAllocator::init]. Must be called exactly once.
+pub unsafe fn init_frame_allocator(memory_regions: &[MemoryRegion], physical_memory_offset: VirtAddr) {
+    let allocator = BitmapFrameAllocator::init(memory_regions, physical_memory_offset);
+    *FRAME_ALLOCATOR.lock() = Some(allocator);
+}
+
+/// Runs `f` with the global frame allocator locked.
+/// Panics if the frame allocator has not been initialized yet.
+pub fn with_frame_allocator<R>(f: impl FnOnce(&mut BitmapFrameAllocator) -> R) -> R {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    f(allocator.as_mut().expect("frame allocator not initialized"))
+}
+
+pub fn allocate_frame() -> Option<PhysFrame> {
+    with_frame_allocator(|allocator| allocator.allocate_frame())
+}
+
+/// # Safety
+/// The frame must have come from [allocate_frame] and must not be in use anymore.
+pub unsafe fn deallocate_frame(frame: PhysFrame) {
+    with_frame_allocator(|allocator| allocator.deallocate_frame(frame))
+}
+
+pub fn allocate_contiguous_frames(count: usize) -> Option<PhysFrameRange> {
+    with_frame_allocator(|allocator| allocator.allocate_contiguous(count))
+}
+
+/// # Safety
+/// The frames must have come from [allocate_contiguous_frames] and must not be in use anymore.
+pub unsafe fn deallocate_contiguous_frames(range: PhysFrameRange) {
+    with_frame_allocator(|allocator| allocator.deallocate_contiguous(range))
+}
+
+/// A handle to the global frame allocator that can be passed wherever the x86_64 crate
+/// expects a `FrameAllocator` (e.g. when creating page table mappings).
+pub struct GlobalFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for GlobalFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        allocate_frame()
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for GlobalFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        deallocate_frame(frame)
+    }
+}
+
+/// Wraps the active level 4 table. See [KernelMapper::new].
+///
+/// # Safety
+/// The complete physical memory must be mapped at `physical_memory_offset`. Must be called once.
+pub unsafe fn init_mapper(physical_memory_offset: VirtAddr) {
+    *MAPPER.lock() = Some(KernelMapper::new(physical_memory_offset));
+}
+
+/// Runs `f` with the global mapper locked.
+/// Panics if the mapper has not been initialized yet.
+pub fn with_mapper<R>(f: impl FnOnce(&mut KernelMapper) -> R) -> R {
+    let mut mapper = MAPPER.lock();
+    f(mapper.as_mut().expect("page table mapper not initialized"))
+}
+
+/// Maps `[start, start + size)` to fresh frames from the global frame allocator.
+pub fn map_range(start: VirtAddr, size: u64, flags: PageTableFlags) -> Result<(), MapError> {
+    with_mapper(|mapper| mapper.map_range(start, size, flags, &mut GlobalFrameAllocator))
+}
+
+/// Unmaps `[start, start + size)` and returns its frames to the global frame allocator.
+///
+/// # Safety
+/// Nothing may use the range anymore, and it must have been mapped with [map_range].
+pub unsafe fn unmap_range(start: VirtAddr, size: u64) -> Result<(), MapError> {
+    with_mapper(|mapper| mapper.unmap_range(start, size, &mut GlobalFrameAllocator))
+}
+
+/// Maps an MMIO region: `size` bytes of physical memory at `phys_start` appear at `start`,
+/// uncached.
+///
+/// # Safety
+/// See [KernelMapper::map_physical_range].
+pub unsafe fn map_mmio(start: VirtAddr, phys_start: PhysAddr, size: u64) -> Result<(), MapError> {
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_EXECUTE;
+    with_mapper(|mapper| mapper.map_physical_range(start, phys_start, size, flags, &mut GlobalFrameAllocator))
+}
+
+/// Removes a mapping made by [map_mmio].
+///
+/// # Safety
+/// Nothing may use the range anymore.
+pub unsafe fn unmap_mmio(start: VirtAddr, size: u64) -> Result<(), MapError> {
+    with_mapper(|mapper| mapper.unmap_physical_range(start, size))
+}
+
+/// Changes the flags of `[start, start + size)`.
+///
+/// # Safety
+/// See [KernelMapper::protect_range].
+pub unsafe fn protect_range(start: VirtAddr, size: u64, flags: PageTableFlags) -> Result<(), MapError> {
+    with_mapper(|mapper| mapper.protect_range(start, size, flags))
+}
+
+pub fn translate(addr: VirtAddr) -> Option<Translation> {
+    with_mapper(|mapper| mapper.translate(addr))
+}
@@ -0,0 +1,292 @@
+use bootloader_api::info::{MemoryRegion, MemoryRegionKind};
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// Size of a single physical frame handed out by the allocator (4 KiB).
+pub const FRAME_SIZE: u64 = 4096;
+
+/// Upper bound on the number of (merged) usable ranges we keep track of.
+/// BIOS memory maps are far below this. Very fragmented UEFI maps may drop the tail.
+const MAX_USABLE_RANGES: usize = 128;
+
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+/// A physical address range `[start, end)` that the bootloader reported as `Usable`.
+/// Both ends are aligned to [FRAME_SIZE].
+#[derive(Debug, Clone, Copy)]
+pub struct UsableRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl UsableRange {
+    const fn empty() -> Self {
+        UsableRange { start: 0, end: 0 }
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        (self.end - self.start) / FRAME_SIZE
+    }
+}
+
+/// Physical frame allocator built from the bootloader memory map.
+///
+/// Only regions of kind [MemoryRegionKind::Usable] are ever handed out.
+/// Every frame between the lowest and the highest usable address gets one bit in a bitmap
+/// (1 = in use / not usable, 0 = free). The bitmap itself lives in the first usable range
+/// that is large enough for it and is reached through the physical memory mapping.
+pub struct BitmapFrameAllocator {
+    ranges: [UsableRange; MAX_USABLE_RANGES],
+    range_count: usize,
+    bitmap: &'static mut [u64],
+    first_frame_number: u64, //frame number represented by bit 0 of the bitmap
+    frame_count: usize,      //number of frames represented by the bitmap
+    usable_frames: usize,
+    free_frames: usize,
+    next_free_hint: usize, //word index where the next single-frame search starts
+}
+
+impl BitmapFrameAllocator {
+    /// Builds the allocator from `memory_regions` (usually `boot_info.memory_regions`).
+    ///
+    /// # Safety
+    /// `physical_memory_offset` must be the virtual address at which the complete physical
+    /// memory is mapped, and the usable regions must really be unused.
+    /// Must only be called once, since the bitmap is written in place.
+    pub unsafe fn init(memory_regions: &[MemoryRegion], physical_memory_offset: VirtAddr) -> Self {
+        //1. collect the usable regions, frame aligned, sorted and merged
+        let mut ranges = [UsableRange::empty(); MAX_USABLE_RANGES];
+        let mut range_count = 0;
+
+        for region in memory_regions.iter() {
+            if region.kind != MemoryRegionKind::Usable {
+                continue;
+            }
+            let start = align_up(region.start, FRAME_SIZE);
+            let end = align_down(region.end, FRAME_SIZE);
+            if start >= end {
+                continue;
+            }
+            insert_range(&mut ranges, &mut range_count, UsableRange { start, end });
+        }
+
+        assert!(range_count > 0, "memory map contains no usable memory");
+
+        //2. work out the size of the bitmap needed to cover lowest..highest usable frame
+        let first_frame_number = ranges[0].start / FRAME_SIZE;
+        let last_frame_number = ranges[range_count - 1].end / FRAME_SIZE;
+        let frame_count = (last_frame_number - first_frame_number) as usize;
+        let word_count = frame_count.div_ceil(BITS_PER_WORD);
+        let bitmap_bytes = align_up((word_count * 8) as u64, FRAME_SIZE);
+
+        //3. carve the bitmap out of the first usable range big enough to hold it
+        let bitmap_range = ranges[..range_count]
+            .iter()
+            .position(|range| range.end - range.start >= bitmap_bytes)
+            .expect("no usable range is large enough for the frame bitmap");
+        let bitmap_phys_start = ranges[bitmap_range].start;
+        let bitmap_ptr = (physical_memory_offset + bitmap_phys_start).as_mut_ptr::<u64>();
+        let bitmap = core::slice::from_raw_parts_mut(bitmap_ptr, word_count);
+
+        //everything starts as "in use"; usable ranges are then cleared
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = BitmapFrameAllocator {
+            ranges,
+            range_count,
+            bitmap,
+            first_frame_number,
+            frame_count,
+            usable_frames: 0,
+            free_frames: 0,
+            next_free_hint: 0,
+        };
+
+        for i in 0..range_count {
+            let range = allocator.ranges[i];
+            let first = allocator.bit_index(range.start);
+            for bit in first..first + range.frame_count() as usize {
+                allocator.clear_bit(bit);
+            }
+            allocator.usable_frames += range.frame_count() as usize;
+        }
+        allocator.free_frames = allocator.usable_frames;
+
+        //finally, the frames holding the bitmap are no longer free
+        let first = allocator.bit_index(bitmap_phys_start);
+        for bit in first..first + (bitmap_bytes / FRAME_SIZE) as usize {
+            allocator.set_bit(bit);
+        }
+        allocator.free_frames -= (bitmap_bytes / FRAME_SIZE) as usize;
+
+        allocator
+    }
+
+    /// The usable ranges this allocator was built from.
+    pub fn usable_ranges(&self) -> &[UsableRange] {
+        &self.ranges[..self.range_count]
+    }
+
+    /// Number of usable frames (including the ones used by the bitmap itself).
+    pub fn usable_frames(&self) -> usize {
+        self.usable_frames
+    }
+
+    /// Number of frames still available for allocation.
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Allocates `count` physically contiguous frames.
+    /// Returns None if no run of that length is free.
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrameRange> {
+        if count == 0 || count > self.free_frames {
+            return None;
+        }
+
+        let mut run_start = 0;
+        let mut run_length = 0;
+        let mut bit = 0;
+        while bit < self.frame_count {
+            //skip full words quickly when we are not inside a run
+            if run_length == 0 && bit % BITS_PER_WORD == 0 && self.bitmap[bit / BITS_PER_WORD] == u64::MAX {
+                bit += BITS_PER_WORD;
+                continue;
+            }
+            if self.is_set(bit) {
+                run_length = 0;
+            } else {
+                if run_length == 0 {
+                    run_start = bit;
+                }
+                run_length += 1;
+                if run_length == count {
+                    for used in run_start..run_start + count {
+                        self.set_bit(used);
+                    }
+                    self.free_frames -= count;
+                    let start = self.frame_at(run_start);
+                    return Some(PhysFrame::range(start, start + count as u64));
+                }
+            }
+            bit += 1;
+        }
+        None
+    }
+
+    /// Returns a run of frames obtained from [Self::allocate_contiguous].
+    ///
+    /// # Safety
+    /// The frames must not be in use anymore.
+    pub unsafe fn deallocate_contiguous(&mut self, range: PhysFrameRange) {
+        for frame in range {
+            self.deallocate_frame(frame);
+        }
+    }
+
+    fn bit_index(&self, phys_addr: u64) -> usize {
+        (phys_addr / FRAME_SIZE - self.first_frame_number) as usize
+    }
+
+    fn frame_at(&self, bit: usize) -> PhysFrame {
+        let addr = (self.first_frame_number + bit as u64) * FRAME_SIZE;
+        PhysFrame::containing_address(PhysAddr::new(addr))
+    }
+
+    fn is_set(&self, bit: usize) -> bool {
+        self.bitmap[bit / BITS_PER_WORD] & (1 << (bit % BITS_PER_WORD)) != 0
+    }
+
+    fn set_bit(&mut self, bit: usize) {
+        self.bitmap[bit / BITS_PER_WORD] |= 1 << (bit % BITS_PER_WORD);
+    }
+
+    fn clear_bit(&mut self, bit: usize) {
+        self.bitmap[bit / BITS_PER_WORD] &= !(1 << (bit % BITS_PER_WORD));
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if self.free_frames == 0 {
+            return None;
+        }
+        let word_count = self.bitmap.len();
+        //start at the hint and wrap around once
+        for offset in 0..word_count {
+            let word_index = (self.next_free_hint + offset) % word_count;
+            let word = self.bitmap[word_index];
+            if word == u64::MAX {
+                continue;
+            }
+            let bit = word_index * BITS_PER_WORD + word.trailing_ones() as usize;
+            if bit >= self.frame_count {
+                continue; //padding bits at the end of the last word
+            }
+            self.set_bit(bit);
+            self.free_frames -= 1;
+            self.next_free_hint = word_index;
+            return Some(self.frame_at(bit));
+        }
+        None
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let addr = frame.start_address().as_u64();
+        let is_usable = self
+            .usable_ranges()
+            .iter()
+            .any(|range| range.start <= addr && addr < range.end);
+        assert!(is_usable, "deallocating frame {:?} which is not usable memory", frame);
+
+        let bit = self.bit_index(addr);
+        assert!(self.is_set(bit), "frame {:?} deallocated twice", frame);
+        self.clear_bit(bit);
+        self.free_frames += 1;
+        if bit / BITS_PER_WORD < self.next_free_hint {
+            self.next_free_hint = bit / BITS_PER_WORD;
+        }
+    }
+}
+
+//keeps ranges sorted by start address and merges ranges that touch each other
+fn insert_range(ranges: &mut [UsableRange; MAX_USABLE_RANGES], count: &mut usize, new: UsableRange) {
+    let position = ranges[..*count]
+        .iter()
+        .position(|range| range.start > new.start)
+        .unwrap_or(*count);
+
+    //merge with the previous range?
+    if position > 0 && ranges[position - 1].end == new.start {
+        ranges[position - 1].end = new.end;
+        //and possibly with the next one as well
+        if position < *count && ranges[position].start == new.end {
+            ranges[position - 1].end = ranges[position].end;
+            ranges.copy_within(position + 1..*count, position);
+            *count -= 1;
+        }
+        return;
+    }
+    //merge with the next range?
+    if position < *count && ranges[position].start == new.end {
+        ranges[position].start = new.start;
+        return;
+    }
+    if *count == MAX_USABLE_RANGES {
+        return; //out of slots; this memory is simply not used
+    }
+    ranges.copy_within(position..*count, position + 1);
+    ranges[position] = new;
+    *count += 1;
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    (value + align - 1) & !(align - 1)
+}
+
+fn align_down(value: u64, align: u64) -> u64 {
+    value & !(align - 1)
+}
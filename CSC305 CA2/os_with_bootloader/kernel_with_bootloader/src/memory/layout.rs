@@ -0,0 +1,69 @@
+use core::ops::Range;
+
+use bootloader_api::info::MemoryRegion;
+use spin::Once;
+use x86_64::structures::paging::Page;
+use x86_64::VirtAddr;
+
+use super::frame_allocator::FRAME_SIZE;
+
+/// Where the bootloader put things in the kernel's address space. Recorded once at boot,
+/// so exception handlers can say what an address belongs to without taking any locks.
+#[derive(Debug, Clone)]
+pub struct KernelLayout {
+    /// All of physical memory, mapped by the bootloader (see `Mapping::Dynamic` in main.rs).
+    pub physical_memory: Range<VirtAddr>,
+    /// The loaded kernel image. The end is approximate: the bootloader only tells us the ELF file size.
+    pub kernel_image: Range<VirtAddr>,
+    /// The boot stack my_entry_point runs on.
+    pub kernel_stack: Range<VirtAddr>,
+    /// The unmapped page right below the boot stack. Touching it means the stack overflowed.
+    pub kernel_stack_guard: Page,
+}
+
+static LAYOUT: Once<KernelLayout> = Once::new();
+
+/// Records the layout. Call it from my_entry_point after [super::init], because the stack
+/// is found by walking the page tables.
+pub fn init(
+    memory_regions: &[MemoryRegion],
+    physical_memory_offset: VirtAddr,
+    kernel_image_offset: u64,
+    kernel_len: u64,
+) {
+    let physical_memory_end = memory_regions.iter().map(|region| region.end).max().unwrap_or(0);
+    let kernel_image_start = VirtAddr::new(kernel_image_offset);
+
+    //the bootloader maps the stack with one unmapped guard page below it (and doesn't tell us where),
+    //so start at the current stack pointer and walk down until we hit the guard page
+    let stack_pointer = current_stack_pointer();
+    let mut guard_page = Page::containing_address(stack_pointer);
+    super::with_mapper(|mapper| {
+        while mapper.translate(guard_page.start_address()).is_some() {
+            guard_page -= 1;
+        }
+    });
+
+    let stack_start = (guard_page + 1).start_address();
+    let stack_end = (stack_start + crate::BOOTLOADER_CONFIG.kernel_stack_size).align_up(FRAME_SIZE);
+
+    LAYOUT.call_once(|| KernelLayout {
+        physical_memory: physical_memory_offset..physical_memory_offset + physical_memory_end,
+        kernel_image: kernel_image_start..kernel_image_start + kernel_len,
+        kernel_stack: stack_start..stack_end,
+        kernel_stack_guard: guard_page,
+    });
+}
+
+/// The recorded layout, or None if [init] hasn't run yet.
+pub fn layout() -> Option<&'static KernelLayout> {
+    LAYOUT.get()
+}
+
+fn current_stack_pointer() -> VirtAddr {
+    let rsp: u64;
+    unsafe {
+        core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+    }
+    VirtAddr::new(rsp)
+}
@@ -0,0 +1,225 @@
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::mapper::{
+    FlagUpdateError, MapToError, MappedFrame, TranslateResult, UnmapError,
+};
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+    PhysFrame, Size4KiB, Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// Errors returned by the [KernelMapper] range operations.
+/// The page tells which page of the range the operation stopped at.
+#[derive(Debug, Clone, Copy)]
+pub enum MapError {
+    /// The frame allocator ran out of frames (for the page itself or for a page table).
+    OutOfFrames,
+    /// The page is already mapped to the given frame.
+    AlreadyMapped(Page, PhysFrame),
+    /// The page is not mapped.
+    NotMapped(Page),
+    /// The page is part of a 2 MiB / 1 GiB mapping, which this API does not split.
+    HugePage(Page),
+    /// The page table entry points to a physical address that is not valid.
+    InvalidFrameAddress(Page, PhysAddr),
+}
+
+/// Where a virtual address ended up after walking the page tables.
+#[derive(Debug, Clone, Copy)]
+pub struct Translation {
+    pub phys_addr: PhysAddr,
+    pub page_size: u64,
+    pub flags: PageTableFlags,
+}
+
+/// Wraps the active level 4 page table (the one the bootloader left in CR3) in an
+/// [OffsetPageTable], using the dynamic mapping of physical memory to reach the tables.
+///
+/// All range operations work on 4 KiB pages; `start` is rounded down and `size` rounded up
+/// to whole pages.
+pub struct KernelMapper {
+    table: OffsetPageTable<'static>,
+}
+
+impl KernelMapper {
+    /// # Safety
+    /// The complete physical memory must be mapped at `physical_memory_offset`.
+    /// Only one KernelMapper may exist, otherwise the level 4 table is aliased mutably.
+    pub unsafe fn new(physical_memory_offset: VirtAddr) -> Self {
+        let (level_4_table_frame, _) = Cr3::read();
+        let virt = physical_memory_offset + level_4_table_frame.start_address().as_u64();
+        let level_4_table: &'static mut PageTable = &mut *virt.as_mut_ptr();
+
+        KernelMapper {
+            table: OffsetPageTable::new(level_4_table, physical_memory_offset),
+        }
+    }
+
+    /// Virtual address through which the given physical address can be reached.
+    pub fn phys_to_virt(&self, phys_addr: PhysAddr) -> VirtAddr {
+        self.table.phys_offset() + phys_addr.as_u64()
+    }
+
+    /// Maps `[start, start + size)` to freshly allocated frames.
+    /// On error, the pages mapped so far by this call are unmapped and their frames returned.
+    pub fn map_range<A>(
+        &mut self,
+        start: VirtAddr,
+        size: u64,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<(), MapError>
+    where
+        A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+    {
+        let pages = page_range(start, size);
+        for (index, page) in pages.enumerate() {
+            let result = match frame_allocator.allocate_frame() {
+                Some(frame) => {
+                    let mapped = unsafe { self.table.map_to(page, frame, flags, frame_allocator) };
+                    mapped.map(|flush| flush.flush()).map_err(|err| {
+                        unsafe { frame_allocator.deallocate_frame(frame) };
+                        map_to_error(page, err)
+                    })
+                }
+                None => Err(MapError::OutOfFrames),
+            };
+
+            if let Err(err) = result {
+                //roll back what we already did, so that the caller does not end up with half a range
+                for mapped_page in pages.take(index) {
+                    if let Ok((frame, flush)) = self.table.unmap(mapped_page) {
+                        flush.flush();
+                        unsafe { frame_allocator.deallocate_frame(frame) };
+                    }
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps `[start, start + size)` to the physical range starting at `phys_start`,
+    /// e.g. for MMIO registers. The frames are not taken from the frame allocator
+    /// (it only needs to provide frames for new page tables).
+    /// On error, the pages mapped so far by this call are unmapped again.
+    ///
+    /// # Safety
+    /// The caller must make sure the physical range may be accessed through this mapping
+    /// (for MMIO usually with `NO_CACHE`) and does not alias memory used as normal RAM.
+    pub unsafe fn map_physical_range<A>(
+        &mut self,
+        start: VirtAddr,
+        phys_start: PhysAddr,
+        size: u64,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<(), MapError>
+    where
+        A: FrameAllocator<Size4KiB>,
+    {
+        let first_frame = PhysFrame::<Size4KiB>::containing_address(phys_start);
+        let pages = page_range(start, size);
+        for (index, page) in pages.enumerate() {
+            let frame = first_frame + index as u64;
+            let result = self.table.map_to(page, frame, flags, frame_allocator);
+            if let Err(err) = result.map(|flush| flush.flush()) {
+                //roll back like map_range, but the frames aren't ours to free
+                for mapped_page in pages.take(index) {
+                    if let Ok((_, flush)) = self.table.unmap(mapped_page) {
+                        flush.flush();
+                    }
+                }
+                return Err(map_to_error(page, err));
+            }
+        }
+        Ok(())
+    }
+
+    /// Unmaps `[start, start + size)` and hands the frames back to `frame_deallocator`.
+    /// Use [Self::unmap_physical_range] for ranges set up with [Self::map_physical_range].
+    pub fn unmap_range<D>(&mut self, start: VirtAddr, size: u64, frame_deallocator: &mut D) -> Result<(), MapError>
+    where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        for page in page_range(start, size) {
+            let (frame, flush) = self.table.unmap(page).map_err(|err| unmap_error(page, err))?;
+            flush.flush();
+            unsafe { frame_deallocator.deallocate_frame(frame) };
+        }
+        Ok(())
+    }
+
+    /// Unmaps `[start, start + size)` without freeing the frames behind it.
+    pub fn unmap_physical_range(&mut self, start: VirtAddr, size: u64) -> Result<(), MapError> {
+        for page in page_range(start, size) {
+            let (_, flush) = self.table.unmap(page).map_err(|err| unmap_error(page, err))?;
+            flush.flush();
+        }
+        Ok(())
+    }
+
+    /// Replaces the flags of every page in `[start, start + size)`, e.g. to make a range read-only.
+    ///
+    /// # Safety
+    /// Removing `PRESENT` or `WRITABLE` from memory that is still in use will fault.
+    pub unsafe fn protect_range(&mut self, start: VirtAddr, size: u64, flags: PageTableFlags) -> Result<(), MapError> {
+        for page in page_range(start, size) {
+            self.table
+                .update_flags(page, flags)
+                .map(|flush| flush.flush())
+                .map_err(|err| match err {
+                    FlagUpdateError::PageNotMapped => MapError::NotMapped(page),
+                    FlagUpdateError::ParentEntryHugePage => MapError::HugePage(page),
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Walks the page tables for `addr`. Works for huge pages as well.
+    pub fn translate(&self, addr: VirtAddr) -> Option<Translation> {
+        match self.table.translate(addr) {
+            TranslateResult::Mapped { frame, offset, flags } => Some(Translation {
+                phys_addr: frame.start_address() + offset,
+                page_size: match frame {
+                    MappedFrame::Size4KiB(_) => 4096,
+                    MappedFrame::Size2MiB(_) => 2 * 1024 * 1024,
+                    MappedFrame::Size1GiB(_) => 1024 * 1024 * 1024,
+                },
+                flags,
+            }),
+            TranslateResult::NotMapped | TranslateResult::InvalidFrameAddress(_) => None,
+        }
+    }
+
+    /// True if no page of `[start, start + size)` is mapped.
+    pub fn is_range_unmapped(&self, start: VirtAddr, size: u64) -> bool {
+        page_range(start, size).all(|page| self.translate(page.start_address()).is_none())
+    }
+}
+
+//all 4 KiB pages touched by [start, start + size). None at all for size 0
+fn page_range(start: VirtAddr, size: u64) -> x86_64::structures::paging::page::PageRange {
+    let first = Page::<Size4KiB>::containing_address(start);
+    if size == 0 {
+        return Page::range(first, first);
+    }
+    let end = Page::<Size4KiB>::containing_address(start + (size - 1)) + 1;
+    Page::range(first, end)
+}
+
+fn map_to_error(page: Page, err: MapToError<Size4KiB>) -> MapError {
+    match err {
+        MapToError::FrameAllocationFailed => MapError::OutOfFrames,
+        MapToError::ParentEntryHugePage => MapError::HugePage(page),
+        MapToError::PageAlreadyMapped(frame) => MapError::AlreadyMapped(page, frame),
+    }
+}
+
+fn unmap_error(page: Page, err: UnmapError) -> MapError {
+    match err {
+        UnmapError::PageNotMapped => MapError::NotMapped(page),
+        UnmapError::ParentEntryHugePage => MapError::HugePage(page),
+        UnmapError::InvalidFrameAddress(addr) => MapError::InvalidFrameAddress(page, addr),
+    }
+}
@@ -0,0 +1,17 @@
+//Runs the kernel's self-checks (task::sync::selftest and sync::selftest). There is no test harness for a no_std
+//kernel, so a check is a plain function that panics with the failed assertion and a backtrace.
+//Only built with `--features selftest`, so a normal boot doesn't spend time on them.
+
+use crate::println;
+
+/// A check's name and the function that runs it.
+pub type Check = (&'static str, fn());
+
+/// Prints `title`, then runs each check in turn and prints its name as it passes.
+pub fn run(title: &str, checks: &[Check]) {
+    println!("\n{}", title);
+    for (name, check) in checks {
+        check();
+        println!("  {} ... ok", name);
+    }
+}
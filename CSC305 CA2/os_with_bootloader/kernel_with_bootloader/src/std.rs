@@ -1,7 +1,7 @@
-use alloc::string::{String, ToString};
-
-use crate::interrupts::KEY_PRESSED;
+use alloc::string::String;
 
+pub mod input; //used by input!, confirm! and choose!, wherever they are expanded
+pub(crate) mod line_editor;
 pub(crate) mod prelude;
 
 #[macro_export]
@@ -37,42 +37,78 @@ macro_rules! fastprint {
 }
 
 
-pub fn input_str() -> Option<String> {
-    let mut input: String = "".to_string();
-    let mut input_counter:u32 = 0; //keep a count so that backspaced induced pop is not allowed beyond the count
-    let mut character = *KEY_PRESSED.lock();
+//Typed input (std/input.rs): parses the answer into the type asked for and asks again until it can.
+//let age: u8 = input!("Age: ");
+//let age: u8 = input!("Age: ", default = 18);       //an empty line gives 18
+//let month: u32 = input!("Month: ", range = 1..=12);
+//let name: Option<String> = input!("Name: ", cancel); //None if the user presses Escape
+//The prompt takes format! arguments too: input!("Score for {}: ", name) or input!("Score for {name}: "),
+//and can be any Display value: input!(prompt)
+//A literal prompt is a format string (so inline arguments work); any other prompt is printed as it is
+#[macro_export]
+macro_rules! input {
+    ($prompt:literal, default = $default:expr) => {
+        $crate::std::input::parse_or(&alloc::format!($prompt), $default)
+    };
+    ($prompt:expr, default = $default:expr) => {
+        $crate::std::input::parse_or(&alloc::format!("{}", $prompt), $default)
+    };
+    ($prompt:literal, range = $range:expr) => {
+        $crate::std::input::parse_in(&alloc::format!($prompt), $range)
+    };
+    ($prompt:expr, range = $range:expr) => {
+        $crate::std::input::parse_in(&alloc::format!("{}", $prompt), $range)
+    };
+    ($prompt:literal, cancel) => {
+        $crate::std::input::try_parse(&alloc::format!($prompt))
+    };
+    ($prompt:expr, cancel) => {
+        $crate::std::input::try_parse(&alloc::format!("{}", $prompt))
+    };
+    ($prompt:literal) => {
+        $crate::std::input::parse(&alloc::format!($prompt))
+    };
+    ($prompt:expr) => {
+        $crate::std::input::parse(&alloc::format!("{}", $prompt))
+    };
+    ($($arg:tt)*) => {
+        $crate::std::input::parse(&alloc::format!($($arg)*))
+    };
+}
 
-    while character != Some('\u{000D}') && character != Some('\u{000A}'){//Test for all three breakout conditions
-        match character {
-            None => {
-                //do nothing
-            },
-            Some ('\u{0008}') => {//backspace pressed
-                *KEY_PRESSED.lock() = None; //clear global KEY_PRESSED so that backspace effect is not repeated
-                if input_counter > 0 {
-                    print!("{}", character.unwrap());//visually move backwards
-                    input.pop(); //pop from input
-                    input_counter -=1;
-                }
-                
-            },
-            Some ('\u{000A}') => { //escape pressed. Return None from the function immediately
-                *KEY_PRESSED.lock() = None; //clear global KEY_PRESSED so that effect is not repeated
-                return None;
-            },
-            Some('\u{000D}') => {//Simply breakout of loop if carriage return is pressed.
-                *KEY_PRESSED.lock() = None; //clear global KEY_PRESSED so that effect is not repeated
-                break;
-            },
-            _ => {//Every other unicode key sent, push to input
-                let char_received = character.unwrap().clone();//clone it for keep
-                print!("{}", &char_received);//show char received on console
-                input.push(char_received); //move the character to input
-                input_counter+=1; //keep a count so that backspaced induced pop is not allowed beyond the count
-                *KEY_PRESSED.lock() = None; //clear global KEY_PRESSED after cloning
-            }
-        }
-        character = *KEY_PRESSED.lock(); //read again as long as we have not broken out.
+//Yes/no question: if confirm!("Delete {}?", name) { ... } or confirm!("Continue?", default = true)
+#[macro_export]
+macro_rules! confirm {
+    ($prompt:literal, default = $default:expr) => {
+        $crate::std::input::confirm(&alloc::format!($prompt), Some($default))
+    };
+    ($prompt:expr, default = $default:expr) => {
+        $crate::std::input::confirm(&alloc::format!("{}", $prompt), Some($default))
+    };
+    ($prompt:literal) => {
+        $crate::std::input::confirm(&alloc::format!($prompt), None)
+    };
+    ($prompt:expr) => {
+        $crate::std::input::confirm(&alloc::format!("{}", $prompt), None)
+    };
+    ($($arg:tt)*) => {
+        $crate::std::input::confirm(&alloc::format!($($arg)*), None)
+    };
+}
+
+//Numbered list to pick from, by number or name. Gives the index: choose!("Colour?", &["red", "green"])
+#[macro_export]
+macro_rules! choose {
+    ($prompt:literal, $options:expr) => {
+        $crate::std::input::choose(&alloc::format!($prompt), $options)
     };
-    Some(input) //return the final input string
-}
\ No newline at end of file
+    ($prompt:expr, $options:expr) => {
+        $crate::std::input::choose(&alloc::format!("{}", $prompt), $options)
+    };
+}
+
+//Reads a line, with cursor movement and history: see std/line_editor.rs for the keys.
+//Enter returns it; Escape, Ctrl+C, or Ctrl+D on an empty line return None
+pub fn input_str() -> Option<String> {
+    line_editor::LineEditor::new().read()
+}
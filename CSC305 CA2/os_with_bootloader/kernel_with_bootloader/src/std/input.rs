@@ -0,0 +1,136 @@
+//Typed console input, behind the input!, confirm! and choose! macros in std.rs.
+//Each reads a line with input_str and parses it (FromStr), printing what was wrong and asking again until the
+//answer makes sense.
+
+use core::fmt::Display;
+use core::ops::{Bound, RangeBounds};
+use core::str::FromStr;
+
+use alloc::format;
+use alloc::string::String;
+
+use super::input_str;
+use crate::{print, println};
+
+//Prompts and reads one line. None if it was cancelled (Escape)
+fn ask(prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    let line = input_str();
+    println!();
+    line
+}
+
+/// Asks until the answer parses as a `T`. None if the user cancels with Escape.
+pub fn try_parse<T: FromStr>(prompt: &str) -> Option<T>
+where
+    T::Err: Display,
+{
+    loop {
+        let line = ask(prompt)?;
+        match line.trim().parse() {
+            Ok(value) => return Some(value),
+            Err(error) => println!("'{}' is not valid: {}", line.trim(), error),
+        }
+    }
+}
+
+/// Asks until the answer parses as a `T`, also when cancelled.
+pub fn parse<T: FromStr>(prompt: &str) -> T
+where
+    T::Err: Display,
+{
+    loop {
+        match try_parse(prompt) {
+            Some(value) => return value,
+            None => println!("An answer is needed"),
+        }
+    }
+}
+
+/// Like [parse], but an empty line (or Escape) gives `default`, which the prompt shows.
+pub fn parse_or<T: FromStr + Display>(prompt: &str, default: T) -> T
+where
+    T::Err: Display,
+{
+    let prompt = format!("{}[{}] ", prompt, default);
+    loop {
+        let line = match ask(&prompt) {
+            Some(line) if !line.trim().is_empty() => line,
+            _ => return default,
+        };
+        match line.trim().parse() {
+            Ok(value) => return value,
+            Err(error) => println!("'{}' is not valid: {}", line.trim(), error),
+        }
+    }
+}
+
+//"between 1 and 12", "at least 0", ... for the error message
+fn describe<T: Display>(range: &impl RangeBounds<T>) -> String {
+    match (range.start_bound(), range.end_bound()) {
+        (Bound::Included(start), Bound::Included(end)) => format!("between {} and {}", start, end),
+        (Bound::Included(start), Bound::Excluded(end)) => format!("at least {} and below {}", start, end),
+        (Bound::Included(start), Bound::Unbounded) => format!("at least {}", start),
+        (Bound::Unbounded, Bound::Included(end)) => format!("at most {}", end),
+        (Bound::Unbounded, Bound::Excluded(end)) => format!("below {}", end),
+        _ => String::from("in range"),
+    }
+}
+
+/// Like [parse], but asks again until the value is in `range`, e.g. `1..=12`.
+pub fn parse_in<T, R>(prompt: &str, range: R) -> T
+where
+    T: FromStr + PartialOrd + Display,
+    T::Err: Display,
+    R: RangeBounds<T>,
+{
+    loop {
+        let value = parse(prompt);
+        if range.contains(&value) {
+            return value;
+        }
+        println!("{} is not valid: it has to be {}", value, describe(&range));
+    }
+}
+
+/// Asks a yes/no question. y/yes or n/no in any case; an empty line gives `default` if there is one, and
+/// Escape is no.
+pub fn confirm(prompt: &str, default: Option<bool>) -> bool {
+    let choices = match default {
+        Some(true) => "[Y/n]",
+        Some(false) => "[y/N]",
+        None => "[y/n]",
+    };
+    let prompt = format!("{} {} ", prompt, choices);
+    loop {
+        let Some(line) = ask(&prompt) else { return false };
+        match (line.trim().to_ascii_lowercase().as_str(), default) {
+            ("y" | "yes", _) => return true,
+            ("n" | "no", _) => return false,
+            ("", Some(default)) => return default,
+            _ => println!("Please answer yes or no"),
+        }
+    }
+}
+
+/// Lists `options` with numbers and asks until one is picked, by number or by name (in any case).
+/// Returns its index.
+pub fn choose<T: Display>(prompt: &str, options: &[T]) -> usize {
+    assert!(!options.is_empty(), "choose needs something to choose from");
+    println!("{}", prompt);
+    for (index, option) in options.iter().enumerate() {
+        println!("  {}) {}", index + 1, option);
+    }
+    let prompt = format!("Choice (1-{}): ", options.len());
+    loop {
+        let line = ask(&prompt).unwrap_or_default();
+        let answer = line.trim();
+        let by_number = answer.parse::<usize>().ok().filter(|number| (1..=options.len()).contains(number));
+        let by_number = by_number.map(|number| number - 1);
+        let by_name = || options.iter().position(|option| format!("{}", option).eq_ignore_ascii_case(answer));
+        match by_number.or_else(by_name) {
+            Some(index) => return index,
+            None => println!("'{}' is not one of the choices", answer),
+        }
+    }
+}
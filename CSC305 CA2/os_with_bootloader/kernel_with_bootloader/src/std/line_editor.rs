@@ -0,0 +1,228 @@
+//The line editor behind input_str (and keyboard::read_line for tasks).
+//Keys:
+//  Left/Right, Home/End   move the cursor; typing inserts at the cursor
+//  Backspace/Delete       remove the character before/under the cursor
+//  Ctrl+U                 remove everything before the cursor
+//  Ctrl+W                 remove the word before the cursor
+//  Up/Down                go through earlier lines (the history is shared by every call)
+//  Enter                  done; Escape or Ctrl+C cancel (None); Ctrl+D on an empty line is end of input (None)
+//The line is redrawn in place through FRAME_BUFFER_WRITER: we remember where it starts on the screen and
+//redraw from the first character that changed, with a cursor drawn under the character it is on.
+
+use core::fmt::Write;
+use core::task::Poll;
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::keyboard::{self, KeyCode, KeyEvent};
+use crate::sync::IrqSafeMutex;
+use crate::FRAME_BUFFER_WRITER;
+
+/// How many lines Up/Down can go back to.
+pub const HISTORY_CAPACITY: usize = 32;
+
+//oldest first. keyboard::read_line uses it from a task, which mustn't sleep on a lock; the critical sections are
+//a few VecDeque operations, so it spins (with interrupts off, so a preempted thread never holds it)
+static HISTORY: IrqSafeMutex<VecDeque<String>> = IrqSafeMutex::new(VecDeque::new());
+
+fn add_to_history(line: &str) {
+    let mut history = HISTORY.lock();
+    if line.is_empty() || history.back().is_some_and(|last| last == line) {
+        return;
+    }
+    if history.len() == HISTORY_CAPACITY {
+        history.pop_front();
+    }
+    history.push_back(line.into());
+}
+
+//Ctrl + which letter: a control character (HandleControl::MapLettersToUnicode) or the letter with Ctrl held
+//(HandleControl::Ignore), see keyboard::set_handle_control
+fn ctrl_letter(key: &KeyEvent) -> Option<char> {
+    match key.unicode? {
+        control @ '\u{0001}'..='\u{001A}' => Some((b'a' + control as u8 - 1) as char),
+        letter if key.modifiers.ctrl() && letter.is_ascii_alphabetic() => Some(letter.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+pub struct LineEditor {
+    line: Vec<char>,
+    cursor: usize,                     //index into line
+    start: (usize, usize),             //where line[0] is on the screen
+    drawn: usize,                      //characters on the screen, to blank out the rest when the line gets shorter
+    cursor_at: Option<(usize, usize)>, //where the cursor is drawn
+    browsing: Option<usize>,           //which history entry is shown
+    draft: Vec<char>,                  //the line typed before going through the history
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineEditor {
+    /// Starts an empty line at the current position on the screen.
+    pub fn new() -> LineEditor {
+        let mut editor = LineEditor {
+            line: Vec::new(),
+            cursor: 0,
+            start: FRAME_BUFFER_WRITER.lock().x_y_pos(),
+            drawn: 0,
+            cursor_at: None,
+            browsing: None,
+            draft: Vec::new(),
+        };
+        editor.redraw(0);
+        editor
+    }
+
+    /// Blocks the current thread until the line is done, see the top of the file.
+    pub fn read(mut self) -> Option<String> {
+        loop {
+            if let Poll::Ready(line) = self.handle(keyboard::read_key()) {
+                return line;
+            }
+        }
+    }
+
+    /// Applies a key. Ready when Enter finishes the line (Some) or it was cancelled (None).
+    pub fn handle(&mut self, key: KeyEvent) -> Poll<Option<String>> {
+        match key.code {
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                let line: String = self.line.iter().collect();
+                add_to_history(&line);
+                return self.finish(Some(line));
+            }
+            KeyCode::Escape => return self.finish(None),
+            KeyCode::ArrowLeft => self.move_to(self.cursor.saturating_sub(1)),
+            KeyCode::ArrowRight => self.move_to((self.cursor + 1).min(self.line.len())),
+            KeyCode::Home => self.move_to(0),
+            KeyCode::End => self.move_to(self.line.len()),
+            KeyCode::ArrowUp => self.history_back(),
+            KeyCode::ArrowDown => self.history_forward(),
+            KeyCode::Backspace if self.cursor > 0 => self.remove(self.cursor - 1..self.cursor),
+            KeyCode::Delete if self.cursor < self.line.len() => self.remove(self.cursor..self.cursor + 1),
+            KeyCode::Backspace | KeyCode::Delete => {}
+            _ => match ctrl_letter(&key) {
+                Some('c') => return self.finish(None),
+                Some('d') if self.line.is_empty() => return self.finish(None),
+                Some('d') if self.cursor < self.line.len() => self.remove(self.cursor..self.cursor + 1),
+                Some('u') => self.remove(0..self.cursor),
+                Some('w') => self.remove(self.word_start()..self.cursor),
+                Some(_) => {}
+                None => match key.unicode {
+                    Some(character) if !character.is_control() => self.insert(character),
+                    _ => {} //Shift, F1, Tab, ...
+                },
+            },
+        }
+        Poll::Pending
+    }
+
+    fn insert(&mut self, character: char) {
+        self.line.insert(self.cursor, character);
+        self.cursor += 1;
+        self.redraw(self.cursor - 1);
+    }
+
+    fn remove(&mut self, range: core::ops::Range<usize>) {
+        let from = range.start;
+        self.line.drain(range);
+        self.cursor = from;
+        self.redraw(from);
+    }
+
+    fn move_to(&mut self, cursor: usize) {
+        self.cursor = cursor;
+        self.redraw(self.drawn.max(self.line.len())); //nothing to redraw, only the cursor moves
+    }
+
+    //where the word before the cursor starts, skipping spaces right before the cursor
+    fn word_start(&self) -> usize {
+        let before = &self.line[..self.cursor];
+        let end = before.iter().rposition(|c| !c.is_whitespace()).map_or(0, |i| i + 1);
+        before[..end].iter().rposition(|c| c.is_whitespace()).map_or(0, |i| i + 1)
+    }
+
+    fn history_back(&mut self) {
+        let history = HISTORY.lock();
+        let entry = match self.browsing {
+            None if history.is_empty() => return,
+            None => {
+                self.draft = self.line.clone();
+                history.len() - 1
+            }
+            Some(entry) => entry.saturating_sub(1),
+        };
+        let line = history[entry].chars().collect();
+        drop(history);
+        self.browsing = Some(entry);
+        self.replace(line);
+    }
+
+    fn history_forward(&mut self) {
+        let Some(entry) = self.browsing else { return };
+        let history = HISTORY.lock();
+        let line = match history.get(entry + 1) {
+            Some(line) => {
+                self.browsing = Some(entry + 1);
+                line.chars().collect()
+            }
+            None => {
+                self.browsing = None;
+                core::mem::take(&mut self.draft)
+            }
+        };
+        drop(history);
+        self.replace(line);
+    }
+
+    fn replace(&mut self, line: Vec<char>) {
+        self.line = line;
+        self.cursor = self.line.len();
+        self.redraw(0);
+    }
+
+    //Redraws the line from line[from] on (blanking what is left of a longer line) and moves the cursor
+    fn redraw(&mut self, mut from: usize) {
+        let mut writer = FRAME_BUFFER_WRITER.lock();
+        if let Some((x, y)) = self.cursor_at.take() {
+            writer.set_x_y_pos(Some(x), Some(y));
+            writer.draw_cursor(false);
+        }
+        let end = self.line.len().max(self.drawn);
+        if !writer.fits(writer.pos_after(self.start, end)) {
+            //the line runs off the bottom of the screen: start again at the top, like print! does
+            writer.clear();
+            self.start = writer.x_y_pos();
+            from = 0;
+        }
+        let (x, y) = writer.pos_after(self.start, from);
+        writer.set_x_y_pos(Some(x), Some(y));
+        for index in from..end {
+            let _ = Write::write_char(&mut *writer, self.line.get(index).copied().unwrap_or(' '));
+        }
+        self.drawn = self.line.len();
+
+        let (x, y) = writer.pos_after(self.start, self.cursor);
+        writer.set_x_y_pos(Some(x), Some(y));
+        writer.draw_cursor(true);
+        self.cursor_at = Some((x, y));
+    }
+
+    //Hides the cursor and leaves the screen position after the line, for whatever is printed next
+    fn finish(&mut self, line: Option<String>) -> Poll<Option<String>> {
+        let mut writer = FRAME_BUFFER_WRITER.lock();
+        if let Some((x, y)) = self.cursor_at.take() {
+            writer.set_x_y_pos(Some(x), Some(y));
+            writer.draw_cursor(false);
+        }
+        let (x, y) = writer.pos_after(self.start, self.line.len());
+        writer.set_x_y_pos(Some(x), Some(y));
+        Poll::Ready(line)
+    }
+}
@@ -12,7 +12,7 @@ pub use alloc::vec::Vec;
 pub use alloc::vec;
 pub use core::cell::RefCell;
 pub use alloc::sync::Arc;
-pub use spin::Mutex; //this is for single threaded environment
+pub use crate::sync::{Barrier, Condvar, Mutex, Semaphore}; //these sleep instead of spinning, see sync.rs. spin::Mutex is still there for interrupt handlers
 pub use core::future::Future;
 pub use core::pin::Pin;
 pub use core::task::{Context, Poll};
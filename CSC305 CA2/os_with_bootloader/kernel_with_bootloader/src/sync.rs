@@ -0,0 +1,29 @@
+/*Blocking sync primitives for kernel threads and tasks.
+spin::Mutex (what std/prelude.rs used to hand out) spins while somebody else holds the lock: on our single CPU
+that burns the rest of the time slice doing nothing, and the holder can't even run until the timer preempts us.
+These put the waiting thread or task to sleep in a WaitQueue instead, and wake it when it has a chance:
+- a kernel thread calls e.g. lock(), which parks it until the lock is free
+- a task awaits e.g. lock_async(), which returns Pending until then. A task must not call the blocking versions:
+  it would park the thread that runs the executor, and with it every other task
+Compare task::sync, which is for tasks only but serves waiters strictly in order.
+IrqSafeMutex is the odd one out: it spins, but with interrupts disabled, for data that interrupt handlers share.
+Build with `--features lock-debug` to panic when a thread or task locks a Mutex it already holds, instead of
+blocking forever.
+Ref: https://docs.rs/std/latest/std/sync/index.html and https://www.kernel.org/doc/html/latest/locking/mutex-design.html */
+
+mod barrier;
+mod condvar;
+mod irq_mutex;
+mod mutex;
+mod owner;
+#[cfg(feature = "selftest")]
+pub mod selftest;
+mod semaphore;
+mod wait_queue;
+
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use condvar::Condvar;
+pub use irq_mutex::{IrqSafeMutex, IrqSafeMutexGuard};
+pub use mutex::{Mutex, MutexGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
+pub use wait_queue::{WaitQueue, WaitUntil};
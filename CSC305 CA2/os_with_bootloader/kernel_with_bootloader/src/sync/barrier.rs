@@ -0,0 +1,76 @@
+//A barrier: `count` threads or tasks call wait() and all of them sleep until the last one arrives.
+//It can be used again right away; each round has its own generation number, which waiters sleep on.
+
+use super::wait_queue::WaitQueue;
+use super::IrqSafeMutex;
+
+pub struct Barrier {
+    count: usize,
+    state: IrqSafeMutex<BarrierState>, //so it is never held by a preempted thread
+    queue: WaitQueue,
+}
+
+struct BarrierState {
+    arrived: usize,
+    generation: u64,
+}
+
+/// Returned by [Barrier::wait]. Exactly one waiter per round is the leader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// True for the last one to arrive, e.g. to have one thread print the round's result.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    pub const fn new(count: usize) -> Barrier {
+        Barrier { count, state: IrqSafeMutex::new(BarrierState { arrived: 0, generation: 0 }), queue: WaitQueue::new() }
+    }
+
+    //Counts the caller in. Ok if it was the last one, otherwise the generation to wait on
+    fn arrive(&self) -> Result<(), u64> {
+        let mut state = self.state.lock();
+        state.arrived += 1;
+        if state.arrived < self.count {
+            return Err(state.generation);
+        }
+        state.arrived = 0;
+        state.generation += 1;
+        Ok(())
+    }
+
+    fn generation(&self) -> u64 {
+        self.state.lock().generation
+    }
+
+    /// Blocks the current thread until `count` threads and tasks have called wait.
+    pub fn wait(&self) -> BarrierWaitResult {
+        match self.arrive() {
+            Ok(()) => {
+                self.queue.wake_all();
+                BarrierWaitResult(true)
+            }
+            Err(generation) => {
+                self.queue.wait_until(|| self.generation() != generation);
+                BarrierWaitResult(false)
+            }
+        }
+    }
+
+    pub async fn wait_async(&self) -> BarrierWaitResult {
+        match self.arrive() {
+            Ok(()) => {
+                self.queue.wake_all();
+                BarrierWaitResult(true)
+            }
+            Err(generation) => {
+                self.queue.wait_until_async(|| self.generation() != generation).await;
+                BarrierWaitResult(false)
+            }
+        }
+    }
+}
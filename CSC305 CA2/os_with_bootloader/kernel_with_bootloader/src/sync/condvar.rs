@@ -0,0 +1,66 @@
+//A condition variable: wait for some state behind a Mutex to change, without holding the lock meanwhile.
+//Every notify bumps a sequence number. A waiter remembers the number before unlocking and sleeps until it has
+//changed, so a notify that comes between unlocking and going to sleep isn't lost.
+//Like std's Condvar, waiters may wake up without the state having changed: check it again (or use wait_while).
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::mutex::MutexGuard;
+use super::wait_queue::WaitQueue;
+
+pub struct Condvar {
+    sequence: AtomicU64,
+    queue: WaitQueue,
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Condvar {
+    pub const fn new() -> Condvar {
+        Condvar { sequence: AtomicU64::new(0), queue: WaitQueue::new() }
+    }
+
+    /// Unlocks `guard`'s mutex, blocks the current thread until notified and locks it again.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+        let sequence = self.sequence.load(Ordering::Acquire);
+        drop(guard);
+        self.queue.wait_until(|| self.sequence.load(Ordering::Acquire) != sequence);
+        mutex.lock()
+    }
+
+    /// Waits for as long as `condition` returns true for the guarded value.
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// The task version of [Condvar::wait].
+    pub async fn wait_async<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+        let sequence = self.sequence.load(Ordering::Acquire);
+        drop(guard);
+        self.queue.wait_until_async(|| self.sequence.load(Ordering::Acquire) != sequence).await;
+        mutex.lock_async().await
+    }
+
+    pub fn notify_one(&self) {
+        self.sequence.fetch_add(1, Ordering::Release);
+        self.queue.wake_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.sequence.fetch_add(1, Ordering::Release);
+        self.queue.wake_all();
+    }
+}
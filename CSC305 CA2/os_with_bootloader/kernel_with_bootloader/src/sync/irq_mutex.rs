@@ -0,0 +1,92 @@
+//A spinlock for data shared with interrupt handlers.
+//With a plain spin::Mutex, an interrupt that arrives while the interrupted code holds the lock spins forever: the
+//holder can't continue until the handler returns. IrqSafeMutex disables interrupts for as long as it is locked,
+//so on our single CPU a handler never finds it locked, and the holder can't be preempted either.
+//Hold it briefly: the timer and keyboard wait meanwhile. And never block or yield while holding it.
+//Ref: https://www.kernel.org/doc/html/latest/locking/spinlocks.html (spin_lock_irqsave)
+
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+use x86_64::instructions::interrupts;
+
+pub struct IrqSafeMutex<T: ?Sized> {
+    inner: spin::Mutex<T>,
+}
+
+#[must_use = "the lock is released (and interrupts enabled again) as soon as the guard is dropped"]
+pub struct IrqSafeMutexGuard<'a, T: ?Sized> {
+    guard: ManuallyDrop<spin::MutexGuard<'a, T>>,
+    were_enabled: bool, //so nested guards only enable interrupts again when the outermost one is dropped
+}
+
+impl<T> IrqSafeMutex<T> {
+    pub const fn new(value: T) -> IrqSafeMutex<T> {
+        IrqSafeMutex { inner: spin::Mutex::new(value) }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> IrqSafeMutex<T> {
+    pub fn lock(&self) -> IrqSafeMutexGuard<'_, T> {
+        let were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        IrqSafeMutexGuard { guard: ManuallyDrop::new(self.inner.lock()), were_enabled }
+    }
+
+    pub fn try_lock(&self) -> Option<IrqSafeMutexGuard<'_, T>> {
+        let were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        match self.inner.try_lock() {
+            Some(guard) => Some(IrqSafeMutexGuard { guard: ManuallyDrop::new(guard), were_enabled }),
+            None => {
+                if were_enabled {
+                    interrupts::enable();
+                }
+                None
+            }
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    /// No locking needed: `&mut self` means nobody else can have a guard.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+impl<T: Default> Default for IrqSafeMutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized> Deref for IrqSafeMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for IrqSafeMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized> Drop for IrqSafeMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        //unlock first: an interrupt that comes in right after enabling may want the lock
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        if self.were_enabled {
+            interrupts::enable();
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//A Mutex that puts waiters to sleep. An atomic flag says whether it is locked; whoever finds it unlocked
+//after being woken gets it, so the order isn't strictly first come first served.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::owner::OwnerCheck;
+use super::wait_queue::WaitQueue;
+
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicBool,
+    queue: WaitQueue,
+    owner: OwnerCheck,
+    data: UnsafeCell<T>,
+}
+
+//only the guard holder touches the data
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Mutex<T> {
+        Mutex { locked: AtomicBool::new(false), queue: WaitQueue::new(), owner: OwnerCheck::new(), data: UnsafeCell::new(value) }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    fn acquire(&self) -> bool {
+        self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+
+    fn guard(&self) -> MutexGuard<'_, T> {
+        self.owner.acquired();
+        MutexGuard { mutex: self }
+    }
+
+    /// Blocks the current thread until the lock is free. Tasks use [Mutex::lock_async].
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.owner.check("Mutex");
+        self.queue.wait_until(|| self.acquire());
+        self.guard()
+    }
+
+    /// Waits until the lock is free without blocking the executor. The guard may be held across .await.
+    pub async fn lock_async(&self) -> MutexGuard<'_, T> {
+        self.owner.check("Mutex");
+        self.queue.wait_until_async(|| self.acquire()).await;
+        self.guard()
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.acquire().then(|| self.guard())
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// No locking needed: `&mut self` means nobody else can have a guard.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    //for Condvar, which unlocks and locks again around waiting
+    pub(super) fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.owner.released();
+        self.mutex.locked.store(false, Ordering::Release);
+        self.mutex.queue.wake_one();
+    }
+}
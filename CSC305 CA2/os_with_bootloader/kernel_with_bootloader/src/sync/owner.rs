@@ -0,0 +1,62 @@
+//Who holds a lock, for the `lock-debug` feature: a thread or task that locks a Mutex it already holds would
+//wait for itself forever. With the feature on, that panics and names the culprit; without it this is a no-op.
+
+#[cfg(feature = "lock-debug")]
+use crate::{task::{self, TaskId}, thread::{self, ThreadId}};
+
+#[cfg(feature = "lock-debug")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Owner {
+    Thread(ThreadId),
+    Task(TaskId),
+}
+
+#[cfg(feature = "lock-debug")]
+impl Owner {
+    //code running in a task is owned by the task, not by the thread that polls it
+    fn current() -> Owner {
+        match task::current_id() {
+            Some(id) => Owner::Task(id),
+            None => Owner::Thread(thread::current()),
+        }
+    }
+}
+
+pub(super) struct OwnerCheck {
+    #[cfg(feature = "lock-debug")]
+    owner: spin::Mutex<Option<Owner>>,
+}
+
+impl OwnerCheck {
+    pub(super) const fn new() -> OwnerCheck {
+        OwnerCheck {
+            #[cfg(feature = "lock-debug")]
+            owner: spin::Mutex::new(None),
+        }
+    }
+
+    /// Panics if the caller already holds the lock.
+    pub(super) fn check(&self, _lock: &str) {
+        #[cfg(feature = "lock-debug")]
+        {
+            let current = Owner::current();
+            if *self.owner.lock() == Some(current) {
+                panic!("{} locked again by its owner {:?}, which would wait for itself forever", _lock, current);
+            }
+        }
+    }
+
+    pub(super) fn acquired(&self) {
+        #[cfg(feature = "lock-debug")]
+        {
+            *self.owner.lock() = Some(Owner::current());
+        }
+    }
+
+    pub(super) fn released(&self) {
+        #[cfg(feature = "lock-debug")]
+        {
+            *self.owner.lock() = None;
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//Checks for the blocking sync primitives, with real kernel threads (and one task) contending for them.
+//Like task::sync::selftest, each check panics with the failed assertion.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::{Barrier, Condvar, Mutex, Semaphore};
+use crate::selftest::Check;
+use crate::{task, thread};
+
+/// Runs every check and prints its name as it passes. Call after interrupts::init() and thread::init():
+/// the threads sleep, which needs the timer.
+pub fn run() {
+    let checks: [Check; 5] = [
+        ("mutex keeps threads out while one is preempted", mutex),
+        ("semaphore limits concurrent threads", semaphore),
+        ("condvar hands items from producer to consumer", condvar),
+        ("barrier releases a round when the last thread arrives", barrier),
+        ("task waits for a lock a thread holds", task_and_thread),
+    ];
+    crate::selftest::run("Blocking sync primitive checks", &checks);
+}
+
+fn mutex() {
+    let counter = Arc::new(Mutex::new(0));
+    let holders = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..3)
+        .map(|_| {
+            let (counter, holders) = (counter.clone(), holders.clone());
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    let mut value = counter.lock();
+                    assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0, "two threads hold the lock");
+                    let read = *value;
+                    thread::yield_now(); //let the others try to get in
+                    *value = read + 1;
+                    holders.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+        })
+        .collect();
+    workers.into_iter().for_each(|worker| worker.join());
+    assert_eq!(*counter.lock(), 60);
+}
+
+fn semaphore() {
+    let semaphore = Arc::new(Semaphore::new(2));
+    let running = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..5)
+        .map(|_| {
+            let (semaphore, running, most) = (semaphore.clone(), running.clone(), most.clone());
+            thread::spawn(move || {
+                let _permit = semaphore.acquire();
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                thread::sleep_ms(5);
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    workers.into_iter().for_each(|worker| worker.join());
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+    assert_eq!(semaphore.available_permits(), 2);
+}
+
+fn condvar() {
+    let queue = Arc::new((Mutex::new(VecDeque::new()), Condvar::new()));
+    let consumer = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            let (items, ready) = &*queue;
+            let mut received = Vec::new();
+            while received.len() < 10 {
+                let mut items = ready.wait_while(items.lock(), |items| items.is_empty());
+                received.extend(items.drain(..));
+            }
+            received
+        })
+    };
+    let (items, ready) = &*queue;
+    for i in 0..10 {
+        items.lock().push_back(i);
+        ready.notify_one();
+        if i % 3 == 0 {
+            thread::sleep_ms(2); //so the consumer sometimes waits and sometimes finds items already there
+        }
+    }
+    assert_eq!(consumer.join(), (0..10).collect::<Vec<_>>());
+}
+
+fn barrier() {
+    const THREADS: usize = 3;
+    const ROUNDS: usize = 3;
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let arrived = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..THREADS)
+        .map(|n| {
+            let (barrier, arrived) = (barrier.clone(), arrived.clone());
+            thread::spawn(move || {
+                let mut leaders = 0;
+                for round in 0..ROUNDS {
+                    thread::sleep_ms(n as u64); //arrive at different times
+                    arrived.fetch_add(1, Ordering::SeqCst);
+                    if barrier.wait().is_leader() {
+                        leaders += 1;
+                    }
+                    //nobody gets past the barrier before everybody has arrived in this round
+                    assert!(arrived.load(Ordering::SeqCst) >= (round + 1) * THREADS);
+                }
+                leaders
+            })
+        })
+        .collect();
+    let leaders: usize = workers.into_iter().map(|worker| worker.join()).sum();
+    assert_eq!(leaders, ROUNDS);
+}
+
+fn task_and_thread() {
+    let lock = Arc::new(Mutex::new(0));
+    let locked = Arc::new(Semaphore::new(0));
+    let holder = {
+        let (lock, locked) = (lock.clone(), locked.clone());
+        thread::spawn(move || {
+            let mut guard = lock.lock();
+            locked.add_permits(1);
+            thread::sleep_ms(10);
+            *guard += 1;
+        })
+    };
+    locked.acquire().forget(); //the thread has the lock now
+    //the executor keeps halting until the thread lets go of the lock
+    let value = task::block_on(async { *lock.lock_async().await });
+    assert_eq!(value, 1);
+    holder.join();
+}
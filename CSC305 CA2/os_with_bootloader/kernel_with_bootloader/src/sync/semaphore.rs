@@ -0,0 +1,69 @@
+//A counting semaphore: acquire() takes a permit, sleeping until one is free; dropping the permit gives it back.
+//Useful to limit how many threads use something at once, or (with forget() and add_permits()) to count events.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::wait_queue::WaitQueue;
+
+pub struct Semaphore {
+    permits: AtomicUsize,
+    queue: WaitQueue,
+}
+
+/// A permit taken from a [Semaphore]. It goes back when this is dropped.
+#[must_use = "the permit is given back as soon as this is dropped"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Semaphore {
+        Semaphore { permits: AtomicUsize::new(permits), queue: WaitQueue::new() }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Acquire)
+    }
+
+    /// Adds `count` permits and wakes as many waiters.
+    pub fn add_permits(&self, count: usize) {
+        self.permits.fetch_add(count, Ordering::Release);
+        for _ in 0..count {
+            if !self.queue.wake_one() {
+                break;
+            }
+        }
+    }
+
+    fn take(&self) -> bool {
+        self.permits.fetch_update(Ordering::AcqRel, Ordering::Acquire, |permits| permits.checked_sub(1)).is_ok()
+    }
+
+    /// Blocks the current thread until a permit is free. Tasks use [Semaphore::acquire_async].
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.queue.wait_until(|| self.take());
+        SemaphorePermit { semaphore: self }
+    }
+
+    pub async fn acquire_async(&self) -> SemaphorePermit<'_> {
+        self.queue.wait_until_async(|| self.take()).await;
+        SemaphorePermit { semaphore: self }
+    }
+
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.take().then_some(SemaphorePermit { semaphore: self })
+    }
+}
+
+impl SemaphorePermit<'_> {
+    /// Keeps the permit taken for good, e.g. when it stands for an event that has been handled.
+    pub fn forget(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(1);
+    }
+}
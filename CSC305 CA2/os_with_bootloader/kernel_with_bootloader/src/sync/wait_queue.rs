@@ -0,0 +1,149 @@
+//A queue of sleeping threads and tasks, all waiting for the same thing.
+//Waiting means: check a condition and, if it doesn't hold yet, go to sleep until woken, then check again.
+//The check and going to sleep happen with interrupts disabled, so on our single CPU nobody can change the
+//condition and wake the queue in between (the lost wakeup problem).
+//Being woken is only a hint that the condition may hold now; someone else may have got there first.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use alloc::collections::VecDeque;
+use x86_64::instructions::interrupts;
+
+use super::IrqSafeMutex;
+use crate::thread::{self, ThreadId};
+
+enum Waiter {
+    Thread(ThreadId),
+    Task { id: u64, waker: Waker },
+}
+
+impl Waiter {
+    fn wake(self) {
+        match self {
+            Waiter::Thread(id) => thread::unpark(id),
+            Waiter::Task { waker, .. } => waker.wake(),
+        }
+    }
+}
+
+pub struct WaitQueue {
+    waiters: IrqSafeMutex<VecDeque<Waiter>>, //interrupt handlers may wake the queue
+    next_id: AtomicU64,                      //tells task waiters apart, so a dropped one can take itself out
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitQueue {
+    pub const fn new() -> WaitQueue {
+        WaitQueue { waiters: IrqSafeMutex::new(VecDeque::new()), next_id: AtomicU64::new(0) }
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut VecDeque<Waiter>) -> R) -> R {
+        f(&mut self.waiters.lock())
+    }
+
+    /// Blocks the current thread until `condition` returns true. `condition` runs with interrupts disabled,
+    /// so keep it to a quick look at some atomics.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        loop {
+            let done = interrupts::without_interrupts(|| {
+                if condition() {
+                    return true;
+                }
+                thread::park(|id| self.with_waiters(|waiters| waiters.push_back(Waiter::Thread(id))));
+                false
+            });
+            if done {
+                return;
+            }
+        }
+    }
+
+    /// The task version of [WaitQueue::wait_until]: resolves once `condition` returns true.
+    pub fn wait_until_async<C: FnMut() -> bool + Unpin>(&self, condition: C) -> WaitUntil<'_, C> {
+        WaitUntil { queue: self, condition, registered: None }
+    }
+
+    /// Wakes the longest waiting thread or task. Returns false if nobody was waiting.
+    pub fn wake_one(&self) -> bool {
+        //wake after unlocking: waking a thread locks the scheduler
+        match self.with_waiters(|waiters| waiters.pop_front()) {
+            Some(waiter) => {
+                waiter.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wakes everybody waiting. Returns how many that were.
+    pub fn wake_all(&self) -> usize {
+        let waiters = self.with_waiters(core::mem::take);
+        let count = waiters.len();
+        waiters.into_iter().for_each(Waiter::wake);
+        count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.with_waiters(|waiters| waiters.is_empty())
+    }
+
+    fn register(&self, waker: &Waker) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.with_waiters(|waiters| waiters.push_back(Waiter::Task { id, waker: waker.clone() }));
+        id
+    }
+
+    //Takes a task waiter out. False if it was already woken
+    fn remove(&self, id: u64) -> bool {
+        self.with_waiters(|waiters| {
+            let position = waiters.iter().position(|waiter| matches!(waiter, Waiter::Task { id: other, .. } if *other == id));
+            position.map(|position| waiters.remove(position)).is_some()
+        })
+    }
+}
+
+/// Future returned by [WaitQueue::wait_until_async].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WaitUntil<'a, C> {
+    queue: &'a WaitQueue,
+    condition: C,
+    registered: Option<u64>,
+}
+
+impl<C: FnMut() -> bool + Unpin> Future for WaitUntil<'_, C> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        interrupts::without_interrupts(|| {
+            if let Some(id) = this.registered.take() {
+                this.queue.remove(id); //register again below with the latest waker, at the back
+            }
+            if (this.condition)() {
+                return Poll::Ready(());
+            }
+            this.registered = Some(this.queue.register(cx.waker()));
+            Poll::Pending
+        })
+    }
+}
+
+impl<C> Drop for WaitUntil<'_, C> {
+    fn drop(&mut self) {
+        //Woken but dropped before it could use the wakeup (e.g. the task was aborted): pass it on,
+        //or the next waiter would sleep while the lock is free
+        if let Some(id) = self.registered {
+            if !self.queue.remove(id) {
+                self.queue.wake_one();
+            }
+        }
+    }
+}
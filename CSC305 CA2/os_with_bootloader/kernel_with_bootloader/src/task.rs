@@ -1,25 +1,74 @@
+pub mod array_queue;
+pub mod executor;
+pub mod global;
+pub mod join;
+pub mod join_handle;
 pub mod simple_executor;
+pub mod sync;
+pub mod timer;
 
+pub use global::{block_on, run, spawn};
+pub use join::join_all;
+pub use join_handle::{Aborted, JoinHandle};
+pub use timer::{interval, sleep, sleep_until, Delay, Interval, Sleep};
+
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::{future::Future, pin::Pin};
 use alloc::boxed::Box;
 
+//Every task gets a unique id, so the executor can tell which task a Waker belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// The task being polled, if this code runs inside one. Kept per kernel thread (see thread::current_task).
+pub fn current_id() -> Option<TaskId> {
+    crate::thread::current_task()
+}
+
 pub struct Task {
+    id: TaskId,
     future: Pin<Box<dyn Future<Output = ()>>>,
 }
 
 impl Task {
     pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
         Task {
+            id: TaskId::new(),
             future: Box::pin(future),
         }
     }
+
+    /// A task for a future with any output, plus a handle to await that output (or abort the task).
+    pub fn with_handle<F>(future: F) -> (Task, JoinHandle<F::Output>)
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let (future, handle) = join_handle::joinable(future);
+        (Task::new(future), handle)
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
 }
 
 use core::task::{Context, Poll};
 
 impl Task {
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+        //put back whatever was there, in case a task drives another executor (SimpleExecutor inside a task)
+        let outer = crate::thread::swap_current_task(Some(self.id));
+        let poll = self.future.as_mut().poll(context);
+        crate::thread::swap_current_task(outer);
+        poll
     }
 }
 
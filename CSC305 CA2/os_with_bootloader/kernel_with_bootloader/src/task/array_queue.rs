@@ -0,0 +1,119 @@
+/*A bounded lock-free queue, usable from interrupt handlers.
+With a spinlock, an interrupt handler that pushes while the interrupted code holds the lock would spin forever.
+Here nobody ever waits for anybody: every slot has a sequence number that says whether it is ready to be written
+(sequence == position) or read (sequence == position + 1), and producers/consumers claim positions with a
+compare-and-swap. push and pop never allocate, so they are fine inside interrupts as well.
+This is Dmitry Vyukov's bounded MPMC queue, the same design as crossbeam's ArrayQueue.
+Ref: https://www.1024cores.net/home/lock-free-algorithms/queues/bounded-mpmc-queue */
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+struct Slot<T> {
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct ArrayQueue<T> {
+    slots: Box<[Slot<T>]>,
+    head: AtomicUsize, //next position to pop
+    tail: AtomicUsize, //next position to push
+}
+
+//the sequence numbers make sure only one thread touches a slot's value at a time
+unsafe impl<T: Send> Send for ArrayQueue<T> {}
+unsafe impl<T: Send> Sync for ArrayQueue<T> {}
+
+impl<T> ArrayQueue<T> {
+    /// A queue that holds up to `capacity` items. Allocates here, never afterwards.
+    pub fn new(capacity: usize) -> ArrayQueue<T> {
+        assert!(capacity > 0, "capacity must not be 0");
+        let slots: Vec<Slot<T>> = (0..capacity)
+            .map(|index| Slot {
+                sequence: AtomicUsize::new(index),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        ArrayQueue {
+            slots: slots.into_boxed_slice(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Adds `value` at the back, or gives it back if the queue is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut position = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % self.capacity()];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            if sequence == position {
+                //the slot is free: try to claim the position
+                match self.tail.compare_exchange_weak(position, position.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence.store(position.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => position = current,
+                }
+            } else if (sequence as isize).wrapping_sub(position as isize) < 0 {
+                return Err(value); //the slot still holds an item from the last lap: full
+            } else {
+                position = self.tail.load(Ordering::Relaxed); //another producer got there first
+            }
+        }
+    }
+
+    /// Takes the item at the front, if there is one.
+    pub fn pop(&self) -> Option<T> {
+        let mut position = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % self.capacity()];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let filled = position.wrapping_add(1);
+            if sequence == filled {
+                match self.head.compare_exchange_weak(position, filled, Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        //free the slot for the producer one lap ahead
+                        slot.sequence.store(position.wrapping_add(self.capacity()), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => position = current,
+                }
+            } else if (sequence as isize).wrapping_sub(filled as isize) < 0 {
+                return None; //nothing written here yet: empty
+            } else {
+                position = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// May be out of date by the time the caller looks at it, unless interrupts are disabled
+    /// and the caller is the only consumer.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        //head first: it can only have moved further by the time tail is read, so tail - head never underflows
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(self.capacity())
+    }
+}
+
+impl<T> Drop for ArrayQueue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
@@ -0,0 +1,179 @@
+/*An executor that only polls tasks that have been woken.
+Every task gets a Waker that pushes the task's id into the wake queue. The executor polls the tasks in that
+queue and otherwise halts the CPU until the next interrupt (which is what wakes tasks: timer, keyboard, ...).
+Compare with SimpleExecutor, which polls every pending task over and over with a waker that does nothing.
+Ref: https://os.phil-opp.com/async-await/#executor-with-waker-support */
+
+use core::future::Future;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Waker};
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use x86_64::instructions::interrupts;
+
+use super::array_queue::ArrayQueue;
+use super::{JoinHandle, Task, TaskId};
+
+/// How many tasks the wake queue holds. A task is in it at most once, however often it is woken before it is
+/// polled. When more tasks than this are ready at once, the executor polls all of them instead (see `overflowed`).
+const WAKE_QUEUE_CAPACITY: usize = 100;
+
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    wake_queue: Arc<ArrayQueue<TaskId>>,
+    //set by a wake that didn't fit in the wake queue. Wakes come from interrupt handlers too, which can't wait
+    //or allocate a bigger queue, so the next run polls every task instead
+    overflowed: Arc<AtomicBool>,
+    wakers: BTreeMap<TaskId, Arc<TaskWaker>>, //one per task, so a Waker isn't allocated on every poll
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor {
+    pub fn new() -> Executor {
+        Executor {
+            tasks: BTreeMap::new(),
+            wake_queue: Arc::new(ArrayQueue::new(WAKE_QUEUE_CAPACITY)),
+            overflowed: Arc::new(AtomicBool::new(false)),
+            wakers: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a future as a new task and returns a handle that resolves to its output.
+    /// It is polled for the first time on the next run.
+    pub fn spawn<F>(&mut self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let (task, handle) = Task::with_handle(future);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Adds an already built task. It is polled for the first time on the next run.
+    pub fn spawn_task(&mut self, task: Task) {
+        let task_id = task.id;
+        if self.tasks.insert(task_id, task).is_some() {
+            panic!("task with same ID already in tasks");
+        }
+        let waker = Arc::new(TaskWaker {
+            task_id,
+            wake_queue: self.wake_queue.clone(),
+            overflowed: self.overflowed.clone(),
+            queued: AtomicBool::new(false),
+        });
+        waker.wake_task(); //queue it for its first poll
+        self.wakers.insert(task_id, waker);
+    }
+
+    /// Runs the tasks forever, halting whenever none of them is ready.
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.run_ready_tasks();
+            self.sleep_if_idle();
+        }
+    }
+
+    /// Runs the tasks until all of them have completed, halting whenever none of them is ready.
+    pub fn run_until_complete(&mut self) {
+        while !self.tasks.is_empty() {
+            self.run_ready_tasks();
+            if !self.tasks.is_empty() {
+                self.sleep_if_idle();
+            }
+        }
+    }
+
+    /// Number of tasks that have not completed yet.
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub(super) fn run_ready_tasks(&mut self) {
+        while let Some(task_id) = self.wake_queue.pop() {
+            self.poll_task(task_id);
+        }
+        if self.overflowed.swap(false, Ordering::AcqRel) {
+            //some woken tasks aren't in the queue: poll everyone (a spurious poll is harmless)
+            let task_ids: Vec<TaskId> = self.tasks.keys().copied().collect();
+            for task_id in task_ids {
+                self.poll_task(task_id);
+            }
+        }
+    }
+
+    fn poll_task(&mut self, task_id: TaskId) {
+        let (Some(task), Some(task_waker)) = (self.tasks.get_mut(&task_id), self.wakers.get(&task_id)) else {
+            return; //woken after it completed
+        };
+        //from here on, a wake has to queue the task again
+        task_waker.queued.store(false, Ordering::Release);
+        let waker = Waker::from(task_waker.clone());
+        let mut context = Context::from_waker(&waker);
+        if task.poll(&mut context).is_ready() {
+            self.tasks.remove(&task_id);
+            self.wakers.remove(&task_id);
+        }
+    }
+
+    fn sleep_if_idle(&self) {
+        self.sleep_unless(|| false);
+    }
+
+    /// Halts until the next interrupt, unless a task is ready or `other_work` says there is something else to do.
+    pub(super) fn sleep_unless(&self, other_work: impl Fn() -> bool) {
+        //Before interrupts::init() nothing could wake us up (and enabling interrupts early would be wrong),
+        //so just check again
+        if !interrupts::are_enabled() {
+            core::hint::spin_loop();
+            return;
+        }
+        //An interrupt between the is_empty check and hlt would wake a task but not us.
+        //So check with interrupts disabled, then enable them and halt in one go (`sti; hlt`):
+        //sti only takes effect after the next instruction, so nothing can slip in between.
+        interrupts::disable();
+        if self.wake_queue.is_empty() && !self.overflowed.load(Ordering::Acquire) && !other_work() {
+            interrupts::enable_and_hlt();
+        } else {
+            interrupts::enable();
+        }
+    }
+}
+
+struct TaskWaker {
+    task_id: TaskId,
+    wake_queue: Arc<ArrayQueue<TaskId>>,
+    overflowed: Arc<AtomicBool>,
+    queued: AtomicBool, //already in the wake queue (or covered by `overflowed`), waiting to be polled
+}
+
+impl TaskWaker {
+    fn wake_task(&self) {
+        if self.queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        //can be called from interrupt handlers: ArrayQueue::push doesn't lock or allocate.
+        //If the queue is full, have the executor poll every task next time round
+        if self.wake_queue.push(self.task_id).is_err() {
+            self.overflowed.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_task();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
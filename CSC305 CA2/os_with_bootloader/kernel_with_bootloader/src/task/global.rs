@@ -0,0 +1,174 @@
+/*The kernel-wide executor, so any code can spawn a task without owning an Executor.
+spawn() only queues the task; the global executor picks it up the next time it looks, which is right away if
+it is running (spawn is called from a task) or once run() or block_on() is called.
+Only one thing can drive the global executor at a time: run() at the end of boot, or block_on() from
+synchronous code. block_on() on another kernel thread while it is driven polls just its own future and sleeps in
+between. Calling block_on() inside a task would block the very executor that has to run it, so it panics.
+Ref: https://os.phil-opp.com/async-await/#executor-with-waker-support */
+
+use core::future::Future;
+use core::pin::{pin, Pin};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use x86_64::instructions::interrupts;
+
+use super::executor::Executor;
+use super::{JoinHandle, Task};
+use crate::sync::IrqSafeMutex;
+use crate::thread::{self, ThreadId};
+
+//Task isn't Send, because Executor and Task::new accept any future. Everything in here came through spawn(),
+//which only takes Send futures (with Send outputs), so it is fine to keep these in statics.
+struct SendTask(Task);
+unsafe impl Send for SendTask {}
+
+struct GlobalExecutor(Executor);
+unsafe impl Send for GlobalExecutor {}
+
+//Spawned tasks the global executor hasn't picked up yet. Kernel threads spawn too, and the executor looks at this
+//with interrupts disabled (sleep_unless), so a thread must not be preempted while it holds the lock
+static SPAWNED: IrqSafeMutex<VecDeque<SendTask>> = IrqSafeMutex::new(VecDeque::new());
+//The executor while nobody drives it. Created on first use
+static EXECUTOR: IrqSafeMutex<Option<GlobalExecutor>> = IrqSafeMutex::new(None);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Runs `future` as a task on the global executor. The returned handle can be awaited for its output,
+/// aborted, or just dropped to let the task run on its own.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (task, handle) = Task::with_handle(future);
+    SPAWNED.lock().push_back(SendTask(task));
+    handle
+}
+
+/// Spawns each task on the global executor. Gives the task's JoinHandle, or a tuple of them for several tasks.
+/// ```ignore
+/// thread_spawn!(example_task());
+/// let (first, second) = thread_spawn!(run_future(), sleep_for(100));
+/// ```
+#[macro_export]
+macro_rules! thread_spawn {
+    ($($task:expr),+ $(,)?) => {
+        ($($crate::task::spawn($task)),+)
+    };
+}
+
+/// Runs the global executor forever, halting whenever no task is ready. The end of the boot sequence.
+pub fn run() -> ! {
+    assert!(claim_executor(), "the global executor is already running on another thread");
+    with_executor(|executor| loop {
+        take_spawned(executor);
+        executor.run_ready_tasks();
+        executor.sleep_unless(spawned_waiting);
+    })
+}
+
+/// Runs `future` to completion from synchronous code and returns its output.
+/// Spawned tasks keep running on the global executor in the meantime, so the future may wait for them.
+/// Panics if called inside a task.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    assert!(super::current_id().is_none(), "block_on called inside a task: it would block the executor that runs it");
+    let mut future = pin!(future);
+    if !claim_executor() {
+        return block_on_parked(future); //another thread drives the executor, and runs the spawned tasks
+    }
+    let woken = Arc::new(BlockOnWaker(AtomicBool::new(true))); //true: poll it once to begin with
+    let waker = Waker::from(woken.clone());
+    let mut context = Context::from_waker(&waker);
+
+    with_executor(|executor| loop {
+        if woken.0.swap(false, Ordering::AcqRel) {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+        take_spawned(executor);
+        executor.run_ready_tasks();
+        executor.sleep_unless(|| woken.0.load(Ordering::Acquire) || spawned_waiting());
+    })
+}
+
+//Makes the caller the one driving the global executor. False if somebody already is
+fn claim_executor() -> bool {
+    !RUNNING.swap(true, Ordering::Acquire)
+}
+
+//Takes the executor out of its static for the duration of `f`, so spawn() from inside tasks doesn't
+//deadlock on it. The caller has claimed it (claim_executor), so no tasks are run twice.
+fn with_executor<R>(f: impl FnOnce(&mut Executor) -> R) -> R {
+    let global = EXECUTOR.lock().take(); //not creating the executor with the lock held (and interrupts off)
+    let mut executor = global.map_or_else(Executor::new, |global| global.0);
+    let result = f(&mut executor);
+    *EXECUTOR.lock() = Some(GlobalExecutor(executor));
+    RUNNING.store(false, Ordering::Release);
+    result
+}
+
+fn take_spawned(executor: &mut Executor) {
+    loop {
+        let spawned = SPAWNED.lock().pop_front(); //don't hold the lock while the executor takes it
+        let Some(SendTask(task)) = spawned else {
+            break;
+        };
+        executor.spawn_task(task);
+    }
+}
+
+fn spawned_waiting() -> bool {
+    !SPAWNED.lock().is_empty()
+}
+
+struct BlockOnWaker(AtomicBool);
+
+impl Wake for BlockOnWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+//block_on for a thread while another one drives the executor: poll the future whenever its waker unparks us
+fn block_on_parked<F: Future>(mut future: Pin<&mut F>) -> F::Output {
+    let woken = Arc::new(ParkWaker { thread: thread::current(), woken: AtomicBool::new(true) });
+    let waker = Waker::from(woken.clone());
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if woken.woken.swap(false, Ordering::AcqRel) {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+        //check and park with interrupts off, so a wake in between isn't lost (see sync/wait_queue.rs)
+        interrupts::without_interrupts(|| {
+            if !woken.woken.load(Ordering::Acquire) {
+                thread::park(|_| {});
+            }
+        });
+    }
+}
+
+struct ParkWaker {
+    thread: ThreadId,
+    woken: AtomicBool,
+}
+
+impl Wake for ParkWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        thread::unpark(self.thread);
+    }
+}
@@ -0,0 +1,121 @@
+/*Waiting for several futures at once, the no_std replacement for futures::join! and futures::future::join_all.
+Every future is wrapped in a MaybeDone, which keeps the output once the future completes. Each poll polls every
+future that hasn't completed yet, and the whole thing is ready once all of them are.
+The futures run concurrently within one task. To run them in parallel tasks, spawn them and join their JoinHandles.
+Ref: https://rust-lang.github.io/async-book/06_multiple_futures/02_join.html */
+
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// A future, or its output once it has completed. Used by [join!](crate::join) and [join_all].
+pub enum MaybeDone<F: Future> {
+    Future(F),
+    Done(F::Output),
+    Taken,
+}
+
+impl<F: Future> MaybeDone<F> {
+    pub fn new(future: F) -> Self {
+        MaybeDone::Future(future)
+    }
+
+    /// Polls the future if it hasn't completed yet. True once the output is there.
+    pub fn poll_done(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> bool {
+        //the future is never moved out of the pinned MaybeDone: it is polled in place and dropped by set()
+        let output = match unsafe { self.as_mut().get_unchecked_mut() } {
+            MaybeDone::Future(future) => match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                Poll::Ready(output) => output,
+                Poll::Pending => return false,
+            },
+            MaybeDone::Done(_) => return true,
+            MaybeDone::Taken => panic!("MaybeDone polled after its output was taken"),
+        };
+        self.set(MaybeDone::Done(output));
+        true
+    }
+
+    /// Takes the output out, if the future has completed.
+    pub fn take_output(self: Pin<&mut Self>) -> Option<F::Output> {
+        //only the output is moved, and the output was never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        match this {
+            MaybeDone::Done(_) => match mem::replace(this, MaybeDone::Taken) {
+                MaybeDone::Done(output) => Some(output),
+                _ => unreachable!(),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Awaits all the futures concurrently and evaluates to a tuple of their outputs, in order.
+/// Only usable inside async code, like futures::join!.
+/// ```ignore
+/// let (a, b) = join!(task_a(), task_b());
+/// ```
+#[macro_export]
+macro_rules! join {
+    ($($future:expr),+ $(,)?) => {
+        $crate::join!(@name [] $($future,)+)
+    };
+    //Give every future its own variable. `future` is a different identifier in every expansion of
+    //this rule (macro hygiene), so the names don't clash.
+    (@name [$($named:tt)*] $first:expr, $($rest:expr,)*) => {
+        $crate::join!(@name [$($named)* (future, $first)] $($rest,)*)
+    };
+    (@name [$(($name:ident, $future:expr))+]) => {{
+        $( let mut $name = core::pin::pin!($crate::task::join::MaybeDone::new($future)); )+
+        core::future::poll_fn(|cx| {
+            let mut all_done = true;
+            $( all_done &= $name.as_mut().poll_done(cx); )+
+            if all_done {
+                core::task::Poll::Ready(($( $name.as_mut().take_output().unwrap(), )+))
+            } else {
+                core::task::Poll::Pending
+            }
+        })
+        .await
+    }};
+}
+
+/// Future returned by [join_all].
+pub struct JoinAll<F: Future> {
+    futures: Pin<Box<[MaybeDone<F>]>>,
+}
+
+/// Awaits all the futures concurrently and resolves to their outputs, in order.
+/// Handy for a Vec of JoinHandles.
+pub fn join_all<I>(futures: I) -> JoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    let futures: Box<[_]> = futures.into_iter().map(MaybeDone::new).collect();
+    JoinAll { futures: Box::into_pin(futures) }
+}
+
+impl<F: Future> Future for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Vec<F::Output>> {
+        //the slice stays where the Box put it, so its elements can be pinned one by one
+        let futures = unsafe { self.futures.as_mut().get_unchecked_mut() };
+        let mut all_done = true;
+        for future in futures.iter_mut() {
+            all_done &= unsafe { Pin::new_unchecked(future) }.poll_done(cx);
+        }
+        if !all_done {
+            return Poll::Pending;
+        }
+        let outputs = futures
+            .iter_mut()
+            .map(|future| unsafe { Pin::new_unchecked(future) }.take_output().unwrap())
+            .collect();
+        Poll::Ready(outputs)
+    }
+}
@@ -0,0 +1,150 @@
+/*JoinHandle: lets whoever spawned a task wait for its output, or cancel it.
+A task is type-erased into Task (a future with Output = ()), so the output can't come back through the executor.
+Instead the spawned future is wrapped: when it finishes, the wrapper puts the output into state shared with the
+JoinHandle and wakes whoever is awaiting the handle. abort() sets a flag and wakes the task; the wrapper sees the
+flag on its next poll and completes without polling the future again, which drops it.
+Ref: https://docs.rs/tokio/latest/tokio/task/struct.JoinHandle.html */
+
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+/// Returned by an awaited [JoinHandle] whose task was aborted (or dropped by its executor) before it finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aborted;
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task was aborted")
+    }
+}
+
+struct State<T> {
+    output: Option<T>,
+    finished: bool,           //no more output will come: completed, aborted or dropped
+    aborted: bool,
+    task_waker: Option<Waker>, //to wake the task when it is aborted
+    join_waker: Option<Waker>, //to wake whoever awaits the JoinHandle
+}
+
+/// Waits for a spawned task and resolves to its output.
+/// Dropping the handle doesn't stop the task; it just keeps running on its own (see [JoinHandle::detach]).
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+/// The future the executor actually runs: the spawned future plus the bookkeeping for its JoinHandle.
+pub(super) struct Joinable<F: Future> {
+    future: Pin<Box<F>>,
+    state: Arc<Mutex<State<F::Output>>>,
+}
+
+pub(super) fn joinable<F: Future>(future: F) -> (Joinable<F>, JoinHandle<F::Output>) {
+    let state = Arc::new(Mutex::new(State {
+        output: None,
+        finished: false,
+        aborted: false,
+        task_waker: None,
+        join_waker: None,
+    }));
+    let handle = JoinHandle { state: state.clone() };
+    (Joinable { future: Box::pin(future), state }, handle)
+}
+
+impl<F: Future> Joinable<F> {
+    //Stores the output (if any) and wakes the JoinHandle. Wakers are woken after the lock is released.
+    fn finish(&mut self, output: Option<F::Output>) {
+        let join_waker = {
+            let mut state = self.state.lock();
+            if state.finished {
+                return;
+            }
+            state.output = output;
+            state.finished = true;
+            state.task_waker = None;
+            state.join_waker.take()
+        };
+        if let Some(waker) = join_waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<F: Future> Future for Joinable<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut(); //Joinable is Unpin: the future itself is boxed
+        {
+            let mut state = this.state.lock();
+            if state.aborted {
+                drop(state);
+                this.finish(None);
+                return Poll::Ready(());
+            }
+            if !state.task_waker.as_ref().is_some_and(|waker| waker.will_wake(cx.waker())) {
+                state.task_waker = Some(cx.waker().clone());
+            }
+        }
+        match this.future.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                this.finish(Some(output));
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F: Future> Drop for Joinable<F> {
+    fn drop(&mut self) {
+        //the executor dropped the task before it finished, so nobody should wait for it forever
+        self.finish(None);
+    }
+}
+
+impl<T> JoinHandle<T> {
+    /// Cancels the task. It stops at its next await point (the next time the executor polls it) and its
+    /// future is dropped. Does nothing if the task already finished.
+    pub fn abort(&self) {
+        let task_waker = {
+            let mut state = self.state.lock();
+            if state.finished {
+                return;
+            }
+            state.aborted = true;
+            state.task_waker.take()
+        };
+        if let Some(waker) = task_waker {
+            waker.wake();
+        }
+    }
+
+    /// Lets the task run on its own; its output is dropped when it finishes.
+    pub fn detach(self) {}
+
+    /// True once the task completed, was aborted or was dropped. Awaiting the handle won't wait then.
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().finished
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, Aborted>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T, Aborted>> {
+        let mut state = self.state.lock();
+        if state.finished {
+            return Poll::Ready(state.output.take().ok_or(Aborted));
+        }
+        if !state.join_waker.as_ref().is_some_and(|waker| waker.will_wake(cx.waker())) {
+            state.join_waker = Some(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
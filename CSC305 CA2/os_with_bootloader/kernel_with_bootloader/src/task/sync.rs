@@ -0,0 +1,22 @@
+/*Sync primitives for tasks.
+A spin::Mutex held across an .await deadlocks a single-core cooperative executor: the task holding the lock is
+waiting to be polled again, while the task trying to get the lock spins and never gives the CPU back.
+These primitives put waiting tasks to sleep (by keeping their Waker and returning Pending) and wake them when it is
+their turn, so the guards here may be held across .await.
+They are for task code only; don't use them from interrupt handlers.
+Ref: https://docs.rs/tokio/latest/tokio/sync/index.html */
+
+pub mod mpsc;
+mod mutex;
+mod notify;
+pub mod oneshot;
+mod rwlock;
+#[cfg(feature = "selftest")]
+pub mod selftest;
+mod semaphore;
+mod wait_list;
+
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Acquire, Semaphore, SemaphorePermit};
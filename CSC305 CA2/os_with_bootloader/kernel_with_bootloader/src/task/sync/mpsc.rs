@@ -0,0 +1,325 @@
+//Multi-producer, single-consumer channels between tasks.
+//channel(capacity) is bounded: send() waits while the channel is full, which slows fast producers down to the
+//speed of the consumer, in the order the senders came. unbounded_channel() never makes the sender wait, so its send() isn't async and can be
+//called from ordinary code, at the cost of the queue growing without limit.
+//recv() resolves to None once every sender is gone and the queue is empty.
+
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use futures_util::stream::Stream;
+use spin::Mutex;
+
+use super::wait_list::WaitList;
+
+/// The Receiver is gone. Holds the value that couldn't be sent.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected, //empty, and every sender is gone
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sending on a closed channel")
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "sending on a full channel"),
+            TrySendError::Closed(_) => write!(f, "sending on a closed channel"),
+        }
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "receiving on an empty channel"),
+            TryRecvError::Disconnected => write!(f, "receiving on a closed channel"),
+        }
+    }
+}
+
+struct Chan<T> {
+    queue: VecDeque<T>,
+    capacity: usize, //usize::MAX for unbounded channels
+    senders: usize,
+    receiver_gone: bool,
+    receiver_waker: Option<Waker>,
+    send_waiters: WaitList, //senders waiting for room in a full channel
+    reserved: usize,        //slots kept for senders that were woken but haven't been polled yet
+}
+
+impl<T> Chan<T> {
+    fn push(&mut self, value: T) {
+        self.queue.push_back(value);
+        if let Some(waker) = self.receiver_waker.take() {
+            waker.wake();
+        }
+    }
+
+    //Room for a sender that wasn't woken. Slots kept for woken senders don't count, so nobody overtakes them
+    fn has_room(&self) -> bool {
+        self.queue.len() + self.reserved < self.capacity
+    }
+
+    //Wakes as many waiting senders as there is room for, keeping a slot for each
+    fn wake_senders(&mut self) {
+        while self.has_room() {
+            match self.send_waiters.pop_front() {
+                Some(waiter) => {
+                    self.reserved += 1;
+                    waiter.wake();
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+type Shared<T> = Arc<Mutex<Chan<T>>>;
+
+pub struct Sender<T> {
+    chan: Shared<T>,
+}
+
+pub struct UnboundedSender<T> {
+    chan: Shared<T>,
+}
+
+pub struct Receiver<T> {
+    chan: Shared<T>,
+}
+
+/// A channel holding at most `capacity` values.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "capacity must not be 0");
+    let chan = new_chan(capacity);
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, Receiver<T>) {
+    let chan = new_chan(usize::MAX);
+    (UnboundedSender { chan: chan.clone() }, Receiver { chan })
+}
+
+fn new_chan<T>(capacity: usize) -> Shared<T> {
+    Arc::new(Mutex::new(Chan {
+        queue: VecDeque::new(),
+        capacity,
+        senders: 1,
+        receiver_gone: false,
+        receiver_waker: None,
+        send_waiters: WaitList::new(),
+        reserved: 0,
+    }))
+}
+
+//Both kinds of sender count towards `senders`, so the receiver knows when no more values can come
+fn clone_sender<T>(chan: &Shared<T>) -> Shared<T> {
+    chan.lock().senders += 1;
+    chan.clone()
+}
+
+fn drop_sender<T>(chan: &Shared<T>) {
+    let mut chan = chan.lock();
+    chan.senders -= 1;
+    if chan.senders == 0 {
+        if let Some(waker) = chan.receiver_waker.take() {
+            waker.wake(); //so recv() can return None
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Waits for room in the channel, then sends. Fails if the Receiver is gone.
+    pub fn send(&self, value: T) -> SendFuture<'_, T> {
+        SendFuture { sender: self, value: Some(value), id: None }
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut chan = self.chan.lock();
+        if chan.receiver_gone {
+            Err(TrySendError::Closed(value))
+        } else if !chan.has_room() || !chan.send_waiters.is_empty() {
+            Err(TrySendError::Full(value))
+        } else {
+            chan.push(value);
+            Ok(())
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.chan.lock().receiver_gone
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender { chan: clone_sender(&self.chan) }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        drop_sender(&self.chan);
+    }
+}
+
+impl<T> UnboundedSender<T> {
+    /// Sends right away. Fails if the Receiver is gone.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut chan = self.chan.lock();
+        if chan.receiver_gone {
+            return Err(SendError(value));
+        }
+        chan.push(value);
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.chan.lock().receiver_gone
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        UnboundedSender { chan: clone_sender(&self.chan) }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        drop_sender(&self.chan);
+    }
+}
+
+/// Future returned by [Sender::send].
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    value: Option<T>,
+    id: Option<u64>, //set while waiting for room
+}
+
+impl<T> Unpin for SendFuture<'_, T> {} //the value is only ever moved, never pinned
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError<T>>> {
+        let this = self.get_mut();
+        let mut chan = this.sender.chan.lock();
+        let value = this.value.take().expect("SendFuture polled after completion");
+        if chan.receiver_gone {
+            this.id = None;
+            return Poll::Ready(Err(SendError(value)));
+        }
+        let our_turn = match this.id {
+            None => chan.send_waiters.is_empty() && chan.has_room(), //don't overtake senders that were here first
+            Some(id) if !chan.send_waiters.contains(id) => {
+                chan.reserved -= 1; //woken: wake_senders kept a slot for us
+                true
+            }
+            Some(_) => false,
+        };
+        if our_turn {
+            chan.push(value);
+            this.id = None;
+            return Poll::Ready(Ok(()));
+        }
+        this.value = Some(value);
+        chan.send_waiters.register(&mut this.id, 1, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            //we may have been woken for a slot we are not going to use
+            let mut chan = self.sender.chan.lock();
+            if !chan.send_waiters.remove(id) && !chan.receiver_gone {
+                chan.reserved -= 1;
+            }
+            chan.wake_senders();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// The next value, or None once the channel is empty and every sender is gone.
+    pub async fn recv(&mut self) -> Option<T> {
+        core::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut chan = self.chan.lock();
+        match chan.queue.pop_front() {
+            Some(value) => {
+                chan.wake_senders();
+                Ok(value)
+            }
+            None if chan.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chan.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut chan = self.chan.lock();
+        match chan.queue.pop_front() {
+            Some(value) => {
+                chan.wake_senders();
+                Poll::Ready(Some(value))
+            }
+            None if chan.senders == 0 => Poll::Ready(None),
+            None => {
+                chan.receiver_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let queued = {
+            let mut chan = self.chan.lock();
+            chan.receiver_gone = true;
+            chan.send_waiters.wake_all(); //so they fail instead of waiting forever
+            core::mem::take(&mut chan.queue)
+        };
+        drop(queued); //values still in the queue are dropped outside the lock
+    }
+}
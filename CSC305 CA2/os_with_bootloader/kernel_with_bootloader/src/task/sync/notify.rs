@@ -0,0 +1,115 @@
+//Notify: lets one task tell others "something happened" without sending data along.
+//notify_one() wakes the task waiting longest, or, if nobody is waiting, leaves a permit so the next
+//notified() completes right away and the notification isn't lost. notify_waiters() wakes everybody waiting now.
+
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use spin::Mutex;
+
+use super::wait_list::WaitList;
+
+pub struct Notify {
+    state: Mutex<State>,
+}
+
+struct State {
+    permit: bool,
+    waiters: WaitList,
+    notified_one: Vec<u64>, //waiters woken by notify_one: if they are dropped, the notification goes on
+}
+
+impl Notify {
+    pub const fn new() -> Notify {
+        Notify {
+            state: Mutex::new(State { permit: false, waiters: WaitList::new(), notified_one: Vec::new() }),
+        }
+    }
+
+    /// Completes at the next notification (or right away if a notify_one() permit is waiting).
+    pub fn notified(&self) -> Notified<'_> {
+        Notified { notify: self, id: None }
+    }
+
+    pub fn notify_one(&self) {
+        self.state.lock().notify_one();
+    }
+
+    /// Wakes every task waiting right now. Leaves no permit behind.
+    pub fn notify_waiters(&self) {
+        self.state.lock().waiters.wake_all();
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    fn notify_one(&mut self) {
+        match self.waiters.pop_front() {
+            Some(waiter) => {
+                //pop_front hands out waiters in id order, so the list stays sorted for binary_search
+                self.notified_one.push(waiter.id());
+                waiter.wake();
+            }
+            None => self.permit = true,
+        }
+    }
+
+    //True if `id` was woken by notify_one (forgetting about it)
+    fn take_notified_one(&mut self, id: u64) -> bool {
+        match self.notified_one.binary_search(&id) {
+            Ok(index) => {
+                self.notified_one.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Future returned by [Notify::notified].
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    id: Option<u64>, //set once registered as a waiter
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let mut state = this.notify.state.lock();
+        match this.id {
+            Some(id) if !state.waiters.contains(id) => {
+                //taken off the list: notified
+                state.take_notified_one(id);
+                this.id = None;
+                return Poll::Ready(());
+            }
+            None if state.permit => {
+                state.permit = false;
+                return Poll::Ready(());
+            }
+            _ => {}
+        }
+        state.waiters.register(&mut this.id, 1, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.notify.state.lock();
+            if !state.waiters.remove(id) && state.take_notified_one(id) {
+                state.notify_one(); //woken but never completed: pass it on
+            }
+        }
+    }
+}
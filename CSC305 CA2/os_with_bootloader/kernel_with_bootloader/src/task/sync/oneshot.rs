@@ -0,0 +1,103 @@
+//A channel for exactly one value, e.g. to hand a task the answer to a request.
+//The Receiver is a future that resolves to the value, or to Err(Canceled) if the Sender is dropped without sending.
+
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use alloc::sync::Arc;
+use spin::Mutex;
+
+/// The Sender was dropped without sending a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canceled;
+
+impl fmt::Display for Canceled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "oneshot sender dropped without sending")
+    }
+}
+
+struct State<T> {
+    value: Option<T>,
+    sender_gone: bool,
+    receiver_gone: bool,
+    waker: Option<Waker>,
+}
+
+pub struct Sender<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+pub struct Receiver<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let state = Arc::new(Mutex::new(State { value: None, sender_gone: false, receiver_gone: false, waker: None }));
+    (Sender { state: state.clone() }, Receiver { state })
+}
+
+impl<T> Sender<T> {
+    /// Sends the value, or gives it back if the Receiver is gone.
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut state = self.state.lock();
+        if state.receiver_gone {
+            return Err(value);
+        }
+        state.value = Some(value);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    } //Drop below then sees the value and doesn't report Canceled
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().receiver_gone
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        state.sender_gone = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// The value, if it has been sent already.
+    pub fn try_recv(&mut self) -> Option<Result<T, Canceled>> {
+        let mut state = self.state.lock();
+        match state.value.take() {
+            Some(value) => Some(Ok(value)),
+            None if state.sender_gone => Some(Err(Canceled)),
+            None => None,
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, Canceled>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T, Canceled>> {
+        let mut state = self.state.lock();
+        match state.value.take() {
+            Some(value) => Poll::Ready(Ok(value)),
+            None if state.sender_gone => Poll::Ready(Err(Canceled)),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.state.lock().receiver_gone = true;
+    }
+}
@@ -0,0 +1,257 @@
+//Checks for the sync primitives, run on the kernel's global executor (there is no test harness for a
+//no_std kernel). Each check panics with the failed assertion, so a broken primitive shows up with a backtrace.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll};
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::{mpsc, oneshot, Mutex, Notify, RwLock, Semaphore};
+use crate::join;
+use crate::selftest::Check;
+use crate::task::{self, block_on, join_all};
+
+/// Runs every check and prints its name as it passes. Call after interrupts::init(), like the rest of the
+/// executor code, though nothing here waits for the timer.
+pub fn run() {
+    let checks: [Check; 10] = [
+        ("bounded channel delivers in order and makes senders wait", bounded_channel),
+        ("unbounded channel and closing", unbounded_channel),
+        ("oneshot", oneshot),
+        ("mutex held across await", mutex),
+        ("dropped lock future passes its turn on", mutex_cancel),
+        ("rwlock readers share, writers don't", rwlock),
+        ("semaphore limits concurrency", semaphore),
+        ("notify_one leaves a permit", notify_one),
+        ("notify_waiters wakes everybody", notify_waiters),
+        ("dropped notified future passes the notification on", notify_cancel),
+    ];
+    crate::selftest::run("Sync primitive checks", &checks);
+}
+
+//Returns Pending once, so other tasks get to run in between
+fn yield_now() -> impl Future<Output = ()> {
+    struct YieldNow(bool);
+    impl Future for YieldNow {
+        type Output = ();
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+    YieldNow(false)
+}
+
+fn bounded_channel() {
+    let (sender, mut receiver) = mpsc::channel(2);
+    let producer = task::spawn(async move {
+        for i in 0..10 {
+            sender.send(i).await.unwrap();
+        }
+        //sender dropped here, so the receiver sees the end of the channel
+    });
+    block_on(async {
+        yield_now().await; //let the producer fill the channel
+        assert_eq!(receiver.len(), 2, "the producer should wait once the channel is full");
+        let mut received = Vec::new();
+        while let Some(value) = receiver.recv().await {
+            received.push(value);
+        }
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+        producer.await.unwrap();
+    });
+
+    let (sender, receiver) = mpsc::channel(1);
+    sender.try_send(1).unwrap();
+    assert_eq!(sender.try_send(2), Err(mpsc::TrySendError::Full(2)));
+    drop(receiver);
+    assert_eq!(sender.try_send(3), Err(mpsc::TrySendError::Closed(3)));
+}
+
+fn unbounded_channel() {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let second = sender.clone();
+    for i in 0..100 {
+        sender.send(i).unwrap(); //never waits, so fine outside a task
+    }
+    second.send(100).unwrap();
+    drop(sender);
+    assert_eq!(receiver.try_recv(), Ok(0));
+    drop(second);
+    let rest = block_on(async {
+        let mut rest = Vec::new();
+        while let Some(value) = receiver.recv().await {
+            rest.push(value);
+        }
+        rest
+    });
+    assert_eq!(rest, (1..=100).collect::<Vec<_>>());
+    assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+
+    let (sender, receiver) = mpsc::unbounded_channel::<u32>();
+    drop(receiver);
+    assert!(sender.is_closed());
+    assert_eq!(sender.send(1), Err(mpsc::SendError(1)));
+}
+
+fn oneshot() {
+    let (sender, receiver) = oneshot::channel();
+    task::spawn(async move {
+        yield_now().await;
+        sender.send(42).unwrap();
+    });
+    assert_eq!(block_on(receiver), Ok(42));
+
+    let (sender, receiver) = oneshot::channel::<u32>();
+    task::spawn(async move {
+        yield_now().await;
+        drop(sender);
+    });
+    assert_eq!(block_on(receiver), Err(oneshot::Canceled));
+
+    let (sender, receiver) = oneshot::channel();
+    drop(receiver);
+    assert_eq!(sender.send(1), Err(1));
+}
+
+fn mutex() {
+    let counter = Arc::new(Mutex::new(0));
+    let holders = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..3)
+        .map(|_| {
+            let counter = counter.clone();
+            let holders = holders.clone();
+            task::spawn(async move {
+                for _ in 0..50 {
+                    let mut value = counter.lock().await;
+                    assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0, "two tasks hold the lock");
+                    let read = *value;
+                    yield_now().await; //other tasks run while we hold the lock
+                    *value = read + 1;
+                    holders.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+        })
+        .collect();
+    block_on(join_all(workers));
+    assert_eq!(*counter.try_lock().unwrap(), 150);
+}
+
+fn mutex_cancel() {
+    let lock = Arc::new(Mutex::new(()));
+    let guard = lock.try_lock().unwrap();
+    let waiting = {
+        let lock = lock.clone();
+        task::spawn(async move {
+            let _guard = lock.lock().await;
+        })
+    };
+    let second = {
+        let lock = lock.clone();
+        task::spawn(async move {
+            let _guard = lock.lock().await;
+        })
+    };
+    block_on(async {
+        yield_now().await; //both are waiting now
+        drop(guard); //wakes `waiting`...
+        waiting.abort(); //...which is aborted before it takes the lock
+        second.await.unwrap(); //so the turn has to pass on to `second`
+    });
+}
+
+fn rwlock() {
+    let lock = Arc::new(RwLock::new(0));
+    block_on(async {
+        let first = lock.read().await;
+        let second = lock.read().await;
+        assert!(lock.try_write().is_none());
+        assert_eq!(*first + *second, 0);
+        let writer = {
+            let lock = lock.clone();
+            task::spawn(async move {
+                *lock.write().await += 1;
+            })
+        };
+        yield_now().await;
+        assert!(lock.try_read().is_none(), "a waiting writer should hold up new readers");
+        drop(first);
+        drop(second);
+        writer.await.unwrap();
+        assert_eq!(*lock.read().await, 1);
+    });
+}
+
+fn semaphore() {
+    let semaphore = Arc::new(Semaphore::new(2));
+    let running = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..5)
+        .map(|_| {
+            let (semaphore, running, most) = (semaphore.clone(), running.clone(), most.clone());
+            task::spawn(async move {
+                let _permit = semaphore.acquire().await;
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                yield_now().await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    block_on(join_all(workers));
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+    assert_eq!(semaphore.available_permits(), 2);
+}
+
+fn notify_one() {
+    let notify = Notify::new();
+    notify.notify_one(); //nobody waiting: stored as a permit
+    block_on(notify.notified());
+}
+
+fn notify_waiters() {
+    let notify = Arc::new(Notify::new());
+    let woken = Arc::new(AtomicUsize::new(0));
+    let waiters: Vec<_> = (0..3)
+        .map(|_| {
+            let (notify, woken) = (notify.clone(), woken.clone());
+            task::spawn(async move {
+                notify.notified().await;
+                woken.fetch_add(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    block_on(async {
+        yield_now().await;
+        notify.notify_waiters();
+        join_all(waiters).await;
+    });
+    assert_eq!(woken.load(Ordering::SeqCst), 3);
+}
+
+fn notify_cancel() {
+    let notify = Arc::new(Notify::new());
+    let first = {
+        let notify = notify.clone();
+        task::spawn(async move { notify.notified().await })
+    };
+    let second = {
+        let notify = notify.clone();
+        task::spawn(async move { notify.notified().await })
+    };
+    block_on(async {
+        yield_now().await;
+        notify.notify_one(); //wakes `first`...
+        first.abort(); //...which never completes, so `second` gets the notification
+        let (first, second) = join!(first, second);
+        assert!(first.is_err());
+        second.unwrap();
+    });
+}
@@ -0,0 +1,140 @@
+//A counting semaphore: acquire() waits for a permit, dropping the permit gives it back.
+//Waiters are served in order, so a task asking for many permits isn't starved by tasks asking for one.
+//A waiter's permits are taken for it when it is woken, so a newcomer can't grab them before it gets polled.
+//Mutex and RwLock below are built on it.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use spin::Mutex;
+
+use super::wait_list::WaitList;
+
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+struct State {
+    permits: usize,
+    waiters: WaitList,
+}
+
+impl State {
+    //Hands the free permits to the waiters at the front for as long as they cover them, and wakes them
+    fn wake_ready(&mut self) {
+        while let Some(waiter) = self.waiters.front() {
+            if waiter.need > self.permits {
+                break;
+            }
+            self.permits -= waiter.need;
+            self.waiters.pop_front().unwrap().wake();
+        }
+    }
+}
+
+/// Permits taken from a [Semaphore]. They go back when this is dropped.
+#[must_use = "the permits are given back as soon as this is dropped"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Semaphore {
+        Semaphore {
+            state: Mutex::new(State { permits, waiters: WaitList::new() }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().permits
+    }
+
+    pub fn add_permits(&self, permits: usize) {
+        let mut state = self.state.lock();
+        state.permits += permits;
+        state.wake_ready();
+    }
+
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Waits until `permits` permits are free and takes them all at once.
+    pub fn acquire_many(&self, permits: usize) -> Acquire<'_> {
+        Acquire { semaphore: self, permits, id: None }
+    }
+
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Takes the permits if they are free and nobody is waiting in front of us.
+    pub fn try_acquire_many(&self, permits: usize) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state.lock();
+        if state.waiters.is_empty() && state.permits >= permits {
+            state.permits -= permits;
+            Some(SemaphorePermit { semaphore: self, permits })
+        } else {
+            None
+        }
+    }
+}
+
+impl SemaphorePermit<'_> {
+    /// Keeps the permits taken for good, e.g. when a guard gives them back itself.
+    pub fn forget(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.permits);
+    }
+}
+
+/// Future returned by [Semaphore::acquire] and [Semaphore::acquire_many].
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    id: Option<u64>, //set while in the wait list
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<SemaphorePermit<'a>> {
+        let this = self.get_mut();
+        let mut state = this.semaphore.state.lock();
+        let ours = match this.id {
+            //first poll: take them if they are free and we don't overtake anybody
+            None if state.waiters.is_empty() && state.permits >= this.permits => {
+                state.permits -= this.permits;
+                true
+            }
+            None => false,
+            Some(id) => !state.waiters.contains(id), //taken off the list: wake_ready took our permits for us
+        };
+        if ours {
+            this.id = None;
+            return Poll::Ready(SemaphorePermit { semaphore: this.semaphore, permits: this.permits });
+        }
+        state.waiters.register(&mut this.id, this.permits, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            //we may have been woken and given permits we never took, or we were holding up the waiters behind us
+            let mut state = self.semaphore.state.lock();
+            if !state.waiters.remove(id) {
+                state.permits += self.permits;
+            }
+            state.wake_ready();
+        }
+    }
+}
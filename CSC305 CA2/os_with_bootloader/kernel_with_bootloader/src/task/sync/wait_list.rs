@@ -0,0 +1,80 @@
+//The tasks waiting on a sync primitive, first come first served.
+//Each waiting future gets an id when it first registers. Being taken off the list by pop_front means
+//"you were woken up"; a future that is dropped after that has to pass the wake-up on, or it is lost.
+
+use alloc::collections::VecDeque;
+use core::task::Waker;
+
+pub(super) struct Waiter {
+    id: u64,
+    pub(super) need: usize, //e.g. how many permits the waiter wants
+    waker: Waker,
+}
+
+impl Waiter {
+    pub(super) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(super) fn wake(self) {
+        self.waker.wake();
+    }
+}
+
+pub(super) struct WaitList {
+    next_id: u64,
+    waiters: VecDeque<Waiter>,
+}
+
+impl WaitList {
+    pub(super) const fn new() -> Self {
+        WaitList { next_id: 0, waiters: VecDeque::new() }
+    }
+
+    /// Adds the waiter at the back, or just updates its waker if `id` is already in the list.
+    pub(super) fn register(&mut self, id: &mut Option<u64>, need: usize, waker: &Waker) {
+        if let Some(waiter) = id.and_then(|id| self.waiters.iter_mut().find(|waiter| waiter.id == id)) {
+            if !waiter.waker.will_wake(waker) {
+                waiter.waker = waker.clone();
+            }
+            return;
+        }
+        let new_id = self.next_id;
+        self.next_id += 1;
+        self.waiters.push_back(Waiter { id: new_id, need, waker: waker.clone() });
+        *id = Some(new_id);
+    }
+
+    /// Takes the waiter out. False if it wasn't there any more, i.e. it has already been woken.
+    pub(super) fn remove(&mut self, id: u64) -> bool {
+        match self.waiters.iter().position(|waiter| waiter.id == id) {
+            Some(index) => {
+                self.waiters.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(super) fn contains(&self, id: u64) -> bool {
+        self.waiters.iter().any(|waiter| waiter.id == id)
+    }
+
+    pub(super) fn front(&self) -> Option<&Waiter> {
+        self.waiters.front()
+    }
+
+    pub(super) fn pop_front(&mut self) -> Option<Waiter> {
+        self.waiters.pop_front()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    pub(super) fn wake_all(&mut self) {
+        while let Some(waiter) = self.waiters.pop_front() {
+            waiter.wake();
+        }
+    }
+}
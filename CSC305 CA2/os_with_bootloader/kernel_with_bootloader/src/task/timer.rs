@@ -0,0 +1,189 @@
+/*Timer futures: sleep() and interval() let a task wait for time to pass without busy polling.
+A pending Sleep registers its deadline (in timer ticks, see time.rs) and its task's Waker here.
+The timer interrupt calls wake_expired(), which wakes every task whose deadline has passed, so the
+executor polls it again and the Sleep completes.
+Ref: https://os.phil-opp.com/async-await/ (wakers) */
+
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use alloc::vec::Vec;
+use futures_util::stream::Stream;
+
+use crate::sync::IrqSafeMutex;
+use crate::time;
+
+struct TimerEntry {
+    id: u64,
+    deadline: u64,
+    waker: Waker,
+    fired: bool, //the interrupt already woke this one
+}
+
+//All pending Sleeps. Task code holds the lock with interrupts disabled, so the interrupt handler never finds
+//it locked. Entries are added and removed by the Sleep itself (never by the interrupt handler), so no memory
+//is allocated or freed inside the interrupt.
+static TIMERS: IrqSafeMutex<Vec<TimerEntry>> = IrqSafeMutex::new(Vec::new());
+
+//Earliest deadline that hasn't fired yet, so the handler doesn't have to look at TIMERS on every tick
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Called by the timer interrupt handler after the tick counter has advanced.
+pub(crate) fn wake_expired() {
+    let now = time::ticks();
+    if now < NEXT_DEADLINE.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut timers = TIMERS.lock();
+    let mut next = u64::MAX;
+    for timer in timers.iter_mut().filter(|timer| !timer.fired) {
+        if timer.deadline <= now {
+            timer.fired = true;
+            timer.waker.wake_by_ref(); //the Waker is dropped later by the Sleep, outside the interrupt
+        } else {
+            next = next.min(timer.deadline);
+        }
+    }
+    NEXT_DEADLINE.store(next, Ordering::Relaxed);
+}
+
+fn register(id: Option<u64>, deadline: u64, waker: &Waker) -> u64 {
+    let mut timers = TIMERS.lock();
+    let id = match id.and_then(|id| timers.iter_mut().find(|timer| timer.id == id)) {
+        Some(timer) => {
+            //polled again, maybe by a different task: keep the newest waker
+            if !timer.waker.will_wake(waker) {
+                timer.waker = waker.clone();
+            }
+            timer.fired = false;
+            timer.id
+        }
+        None => {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            timers.push(TimerEntry { id, deadline, waker: waker.clone(), fired: false });
+            id
+        }
+    };
+    NEXT_DEADLINE.fetch_min(deadline, Ordering::Relaxed);
+    id
+}
+
+fn unregister(id: u64) {
+    //take the entry out with the lock held, but drop its Waker after unlocking (and enabling interrupts) again
+    let removed = {
+        let mut timers = TIMERS.lock();
+        let index = timers.iter().position(|timer| timer.id == id);
+        index.map(|index| timers.swap_remove(index))
+    };
+    drop(removed);
+}
+
+/// A future that completes once the tick counter reaches its deadline.
+pub struct Sleep {
+    deadline: u64,
+    id: Option<u64>, //set while registered in TIMERS
+}
+
+/// Another name for [Sleep], for code that thinks of it as a delay.
+pub type Delay = Sleep;
+
+/// Completes after `duration` (rounded up to whole timer ticks).
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(time::ticks().saturating_add(time::duration_to_ticks(duration)))
+}
+
+/// Completes once [time::ticks] reaches `deadline`.
+pub fn sleep_until(deadline: u64) -> Sleep {
+    Sleep { deadline, id: None }
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    /// Moves the deadline, e.g. to reuse the Sleep for the next period of an interval.
+    pub fn reset(&mut self, deadline: u64) {
+        self.deadline = deadline;
+        if let Some(id) = self.id.take() {
+            unregister(id);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if time::ticks() >= this.deadline {
+            if let Some(id) = this.id.take() {
+                unregister(id);
+            }
+            return Poll::Ready(());
+        }
+        this.id = Some(register(this.id, this.deadline, cx.waker()));
+        //the deadline may have passed while we were registering; don't wait a tick for nothing
+        if time::ticks() >= this.deadline {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            unregister(id);
+        }
+    }
+}
+
+/// A stream that yields every `period`. Yields the number of the tick it fired at.
+/// If the task falls behind, missed periods are skipped rather than delivered in a burst.
+pub struct Interval {
+    period: u64, //in ticks
+    sleep: Sleep,
+}
+
+/// The first item comes one `period` from now.
+pub fn interval(period: Duration) -> Interval {
+    let period = time::duration_to_ticks(period).max(1);
+    Interval { period, sleep: sleep_until(time::ticks().saturating_add(period)) }
+}
+
+impl Interval {
+    /// Waits for the next period. The same as `StreamExt::next` but never returns None.
+    pub async fn tick(&mut self) -> u64 {
+        core::future::poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<u64> {
+        match Pin::new(&mut self.sleep).poll(cx) {
+            Poll::Ready(()) => {
+                let fired_at = self.sleep.deadline();
+                let now = time::ticks();
+                let mut next = fired_at.saturating_add(self.period);
+                if next <= now {
+                    next = now.saturating_add(self.period - (now - fired_at) % self.period);
+                }
+                self.sleep.reset(next);
+                Poll::Ready(fired_at)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Stream for Interval {
+    type Item = u64;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u64>> {
+        self.get_mut().poll_tick(cx).map(Some)
+    }
+}
@@ -17,10 +17,9 @@ impl Future for MyFuture {
 }
 
 // FUNCTION TO CREATE AND EXECUTE THE FUTURE
-pub async fn run_future() {
+pub async fn run_future() -> i32 {
     let future = MyFuture { value: 75 };
-    let result = future.await;
-    println!("RESULT: {}", result);
+    future.await // THE RESULT GOES TO WHOEVER AWAITS THE TASK'S JoinHandle
 }
 //You need an executor to run the above. See main.rs
 
@@ -59,3 +58,54 @@ pub async fn run_modify_data(data: Arc<spin::mutex::Mutex<SharedData>>) {
     modify_data(wrapper);
 }
 
+
+//Example 4: waiting for time to pass. The timer interrupt wakes the task when the deadline is reached,
+//so nothing is polled in between (with an executor that uses real wakers)
+use core::time::Duration;
+use crate::task;
+
+#[allow(dead_code)] //only used by the commented-out demos in main.rs
+pub async fn sleep_example() {
+    println!("Going to sleep at {:?}", crate::time::uptime());
+    task::sleep(Duration::from_millis(500)).await;
+    println!("Woke up at {:?}", crate::time::uptime());
+
+    let mut interval = task::interval(Duration::from_millis(200));
+    for _ in 0..3 {
+        let tick = interval.tick().await;
+        println!("Interval fired at tick {}", tick);
+    }
+}
+
+//Example 5: waiting for tasks. Executor::spawn returns a JoinHandle, a future that resolves to the task's output
+//(or to Err(Aborted) if the task was aborted). join! and join_all wait for several futures at once.
+use crate::join;
+use crate::task::JoinHandle;
+use alloc::vec::Vec;
+
+#[allow(dead_code)] //only used by the commented-out demos in main.rs
+pub async fn join_example(result: JoinHandle<i32>, sleepers: Vec<JoinHandle<u64>>, forever: JoinHandle<()>) {
+    forever.abort();
+    let (result, number, forever) = join!(result, async_number(), forever);
+    println!("RESULT: {:?}, async number: {}, aborted task: {:?}", result, number, forever);
+
+    let woke_at = task::join_all(sleepers).await;
+    println!("Sleepers woke up at ticks {:?}", woke_at);
+}
+
+#[allow(dead_code)] //only used by the commented-out demos in main.rs
+pub async fn sleep_for(ms: u64) -> u64 {
+    task::sleep(Duration::from_millis(ms)).await;
+    crate::time::ticks()
+}
+
+//Example 6: the spin::Mutex in Example 3 must never be held across an .await: on our single CPU, the next task
+//that tries to lock it spins forever while the holder waits to be polled again. task::sync::Mutex puts waiting
+//tasks to sleep instead, so its guard can be held across an .await
+#[allow(dead_code)] //only used by the commented-out demos in main.rs
+pub async fn run_modify_data_async(data: Arc<task::sync::Mutex<SharedData>>) {
+    let mut lock = data.lock().await;
+    task::sleep(Duration::from_millis(100)).await; //other tasks run meanwhile; those wanting the lock sleep
+    lock.value += 10;
+    println!("Modified value: {}", lock.value);
+}
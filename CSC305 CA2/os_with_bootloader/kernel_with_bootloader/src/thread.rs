@@ -0,0 +1,300 @@
+/*Preemptive kernel threads.
+Unlike tasks (task.rs), which run until they choose to return Pending, a thread is interrupted by the timer
+whether it likes it or not: every TIME_SLICE_TICKS ticks the timer interrupt saves its registers (including the
+FPU/SSE state) and continues another thread. Each thread has its own stack, so it can be stopped anywhere.
+The code that calls a thread function first (the boot sequence) becomes the "main" thread.
+Ref: https://os.phil-opp.com/async-await/#preemptive-multitasking and https://wiki.osdev.org/Scheduling_Algorithms */
+
+pub mod policy;
+pub mod scheduler;
+pub mod switch;
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::task::TaskId;
+use crate::{println, time};
+use policy::{Policy, DEFAULT_PRIORITY};
+use scheduler::{with_scheduler, Stats, Thread};
+pub use scheduler::State;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for ThreadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f) //keeps the width and alignment asked for
+    }
+}
+
+/// Owns a spawned thread. [JoinHandle::join] waits for it and returns what its function returned.
+/// Dropping the handle detaches the thread: it keeps running and is freed when it finishes.
+pub struct JoinHandle<T> {
+    id: ThreadId,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+type ThreadMain = Box<dyn FnOnce() + Send>;
+
+/// Picks the scheduling policy, e.g. `thread::init(policy::FairShare::new())`. Call it at boot, before
+/// anything else uses threads; otherwise the scheduler has already started with RoundRobin and this panics.
+pub fn init(policy: impl Policy + 'static) {
+    assert!(scheduler::init(Box::new(policy)), "thread::init called after threads were already in use");
+}
+
+/// Starts `f` on a new thread. It runs as soon as the scheduler gets to it (with round-robin, at the
+/// latest after the current thread's time slice).
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Builder::new().spawn(f)
+}
+
+/// Like [spawn], with a name to tell the thread apart in listings.
+pub fn spawn_named<F, T>(name: &'static str, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Builder::new().name(name).spawn(f)
+}
+
+/// Spawns a thread with settings other than the defaults:
+/// `Builder::new().name("worker").priority(12).spawn(|| ...)`
+pub struct Builder {
+    name: &'static str,
+    priority: u8,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder { name: "thread", priority: DEFAULT_PRIORITY }
+    }
+
+    pub fn name(mut self, name: &'static str) -> Builder {
+        self.name = name;
+        self
+    }
+
+    /// See [DEFAULT_PRIORITY] for what the number means to each policy.
+    pub fn priority(mut self, priority: u8) -> Builder {
+        self.priority = priority;
+        self
+    }
+
+    pub fn spawn<F, T>(self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let result = Arc::new(Mutex::new(None));
+        let thread_result = result.clone();
+        let main: ThreadMain = Box::new(move || {
+            let value = f();
+            *thread_result.lock() = Some(value);
+        });
+        //a Box<dyn FnOnce> is two words; box it again to pass it as the one argument of thread_entry
+        let arg = Box::into_raw(Box::new(main)) as usize;
+        let thread = Thread::new(self.name, self.priority, thread_entry, arg);
+
+        let (id, finished) = with_scheduler(|scheduler| (scheduler.add(thread), scheduler.reap_detached()));
+        drop(finished); //free their stacks with interrupts enabled again
+        JoinHandle { id, result }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+extern "C" fn thread_entry(arg: usize) -> ! {
+    let main = unsafe { Box::from_raw(arg as *mut ThreadMain) };
+    main();
+    exit()
+}
+
+/// Ends the current thread. Threads also end this way when their function returns.
+pub fn exit() -> ! {
+    interrupts::without_interrupts(|| {
+        with_scheduler(|scheduler| scheduler.finish_current());
+        switch::yield_cpu();
+    });
+    unreachable!("a finished thread was scheduled again");
+}
+
+/// Gives the rest of the time slice to the next ready thread.
+pub fn yield_now() {
+    with_scheduler(|_| ()); //make sure the scheduler exists, so the yield interrupt has something to switch to
+    switch::yield_cpu();
+}
+
+/// Blocks the current thread for at least `duration`. Other threads run in the meantime.
+pub fn sleep(duration: Duration) {
+    let until = time::ticks().saturating_add(time::duration_to_ticks(duration));
+    interrupts::without_interrupts(|| {
+        with_scheduler(|scheduler| scheduler.block_current(State::Sleeping { until }));
+        switch::yield_cpu(); //back here once the timer interrupt made us ready again
+    });
+}
+
+pub fn sleep_ms(ms: u64) {
+    sleep(Duration::from_millis(ms));
+}
+
+/// Blocks the current thread until [unpark] is called with its id. `register` is called with that id first,
+/// with interrupts disabled, to hand it to whoever will wake the thread up: an unpark can't slip in between.
+/// Wait queues (crate::sync) are built on this.
+pub fn park(register: impl FnOnce(ThreadId)) {
+    interrupts::without_interrupts(|| {
+        let id = with_scheduler(|scheduler| {
+            scheduler.block_current(State::Blocked);
+            scheduler.current()
+        });
+        register(id);
+        switch::yield_cpu(); //back here once unparked
+    });
+}
+
+/// Makes a thread that waits in [park] ready again. Threads that aren't parked aren't affected.
+/// Fine to call from interrupt handlers.
+pub fn unpark(id: ThreadId) {
+    with_scheduler(|scheduler| scheduler.unblock(id));
+}
+
+/// The thread running this code.
+pub fn current() -> ThreadId {
+    with_scheduler(|scheduler| scheduler.current())
+}
+
+//The task the current thread is polling, for task::current_id(). Each thread has its own, so a thread that
+//preempts a task's poll on another thread doesn't count as running inside that task
+pub(crate) fn current_task() -> Option<TaskId> {
+    with_scheduler(|scheduler| scheduler.current_thread().task)
+}
+
+//Task::poll sets it around the poll and puts the previous one back
+pub(crate) fn swap_current_task(task: Option<TaskId>) -> Option<TaskId> {
+    with_scheduler(|scheduler| core::mem::replace(&mut scheduler.current_thread().task, task))
+}
+
+/// The name the current thread was spawned with ("main" for the boot sequence).
+pub fn current_name() -> &'static str {
+    with_scheduler(|scheduler| {
+        let current = scheduler.current();
+        scheduler.threads[&current].name
+    })
+}
+
+/// One line of [ps]: a thread and what it has used so far. Times are in timer ticks (see time::ticks_to_duration).
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadInfo {
+    pub id: ThreadId,
+    pub name: &'static str,
+    pub priority: u8,
+    pub state: State,
+    pub stats: Stats,
+}
+
+/// A snapshot of every thread the scheduler knows, including finished ones nobody has joined yet.
+pub fn ps() -> Vec<ThreadInfo> {
+    with_scheduler(|scheduler| {
+        scheduler
+            .threads
+            .values()
+            .map(|thread| ThreadInfo {
+                id: thread.id,
+                name: thread.name,
+                priority: thread.priority,
+                state: thread.state,
+                stats: thread.stats,
+            })
+            .collect()
+    })
+}
+
+/// The name of the scheduling policy in use.
+pub fn policy_name() -> &'static str {
+    with_scheduler(|scheduler| scheduler.policy.name())
+}
+
+/// Prints [ps] as a table, for the console.
+pub fn print_ps() {
+    let threads = ps(); //print after the scheduler is unlocked: drawing text is slow, and nothing can be scheduled meanwhile
+    println!("policy: {}, uptime {:?}", policy_name(), time::uptime());
+    println!("{:>4} {:<12} {:>4} {:<9} {:>10} {:>9} {:>10}", "ID", "NAME", "PRI", "STATE", "CPU(ms)", "SWITCHES", "WAIT(ms)");
+    for thread in threads {
+        println!(
+            "{:>4} {:<12} {:>4} {:<9} {:>10} {:>9} {:>10}",
+            thread.id,
+            thread.name,
+            thread.priority,
+            thread.state,
+            time::ticks_to_duration(thread.stats.cpu_ticks).as_millis(),
+            thread.stats.switches,
+            time::ticks_to_duration(thread.stats.wait_ticks).as_millis(),
+        );
+    }
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        with_scheduler(|scheduler| {
+            scheduler.threads.get(&self.id).is_none_or(|thread| thread.state == State::Finished)
+        })
+    }
+
+    /// Waits for the thread to finish and returns its function's result.
+    pub fn join(self) -> T {
+        let finished = loop {
+            let finished = interrupts::without_interrupts(|| {
+                let finished = with_scheduler(|scheduler| {
+                    let current = scheduler.current();
+                    assert!(current != self.id, "a thread can't join itself");
+                    let finished = scheduler.reap_or_wait(self.id, current);
+                    if finished.is_none() {
+                        scheduler.block_current(State::Blocked);
+                    }
+                    finished
+                });
+                if finished.is_none() {
+                    switch::yield_cpu(); //made ready again when the thread finishes
+                }
+                finished
+            });
+            if let Some(finished) = finished {
+                break finished;
+            }
+        };
+        drop(finished); //its stack
+        self.result.lock().take().expect("joined thread left no result")
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        let finished = with_scheduler(|scheduler| scheduler.detach(self.id));
+        drop(finished);
+    }
+}
@@ -0,0 +1,197 @@
+/*Scheduling policies: who runs next, and when the running thread has to make room.
+The scheduler (scheduler.rs) does the mechanics - saving contexts, sleeping, joining, statistics - and asks its
+Policy about the order. One is picked at boot with thread::init(); RoundRobin is used if nobody picks.
+Ref: https://wiki.osdev.org/Scheduling_Algorithms and https://docs.kernel.org/scheduler/sched-design-CFS.html */
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use super::ThreadId;
+
+/// How many timer ticks a thread runs before the next ready thread gets the CPU (10 ms at 1000 Hz).
+pub const TIME_SLICE_TICKS: u32 = 10;
+
+/// Priority threads get unless spawned with another one. Higher numbers run first (StaticPriority) or get
+/// a larger share of the CPU (FairShare); RoundRobin ignores them.
+pub const DEFAULT_PRIORITY: u8 = 8;
+
+/// Decides the order ready threads run in. The idle thread is never handed to a policy.
+/// All methods are called with the scheduler locked and interrupts disabled, so keep them short.
+pub trait Policy: Send {
+    /// For listings.
+    fn name(&self) -> &'static str;
+
+    /// `id` is ready to run: it was just spawned, woke up or was preempted.
+    fn enqueue(&mut self, id: ThreadId, priority: u8);
+
+    /// Takes the thread to run next out of the ready threads.
+    fn next(&mut self) -> Option<ThreadId>;
+
+    fn has_ready(&self) -> bool;
+
+    /// The running thread `id` was charged one more timer tick; it has now run `ran` ticks since it was
+    /// switched to. Returns true when it should make room for a ready thread.
+    fn tick(&mut self, id: ThreadId, priority: u8, ran: u32) -> bool;
+
+    /// `id` has finished, forget anything kept about it.
+    fn exit(&mut self, _id: ThreadId) {}
+}
+
+/// Every ready thread gets TIME_SLICE_TICKS in turn.
+pub struct RoundRobin {
+    ready: VecDeque<ThreadId>,
+}
+
+impl RoundRobin {
+    pub fn new() -> RoundRobin {
+        RoundRobin { ready: VecDeque::new() }
+    }
+}
+
+impl Default for RoundRobin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Policy for RoundRobin {
+    fn name(&self) -> &'static str {
+        "round-robin"
+    }
+
+    fn enqueue(&mut self, id: ThreadId, _priority: u8) {
+        self.ready.push_back(id);
+    }
+
+    fn next(&mut self) -> Option<ThreadId> {
+        self.ready.pop_front()
+    }
+
+    fn has_ready(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    fn tick(&mut self, _id: ThreadId, _priority: u8, ran: u32) -> bool {
+        ran >= TIME_SLICE_TICKS
+    }
+}
+
+/// The highest priority ready thread always runs, round-robin among equal priorities. A thread that becomes
+/// ready with a higher priority than the running one takes over at the next tick.
+/// Lower priorities only run when every higher one sleeps or waits, so a busy loop at high priority starves them.
+pub struct StaticPriority {
+    ready: BTreeMap<u8, VecDeque<ThreadId>>,
+}
+
+impl StaticPriority {
+    pub fn new() -> StaticPriority {
+        StaticPriority { ready: BTreeMap::new() }
+    }
+
+    fn highest_ready(&self) -> Option<u8> {
+        self.ready.keys().next_back().copied()
+    }
+}
+
+impl Default for StaticPriority {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Policy for StaticPriority {
+    fn name(&self) -> &'static str {
+        "static-priority"
+    }
+
+    fn enqueue(&mut self, id: ThreadId, priority: u8) {
+        self.ready.entry(priority).or_default().push_back(id);
+    }
+
+    fn next(&mut self) -> Option<ThreadId> {
+        let mut queue = self.ready.last_entry()?;
+        let id = queue.get_mut().pop_front();
+        if queue.get().is_empty() {
+            queue.remove(); //so the last entry is always a priority with someone waiting
+        }
+        id
+    }
+
+    fn has_ready(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    fn tick(&mut self, _id: ThreadId, priority: u8, ran: u32) -> bool {
+        match self.highest_ready() {
+            Some(highest) if highest > priority => true,
+            Some(highest) if highest == priority => ran >= TIME_SLICE_TICKS,
+            _ => false,
+        }
+    }
+}
+
+/// A thread keeps running at least this many ticks before FairShare switches away from it.
+pub const MIN_GRANULARITY_TICKS: u32 = 2;
+
+/// Fair share, after Linux's CFS: every thread has a virtual runtime that grows while it runs, slower for
+/// higher priorities, and the ready thread that has had the least runs next. Nobody starves: a low priority
+/// thread gets less of the CPU, not none of it.
+pub struct FairShare {
+    ready: BTreeSet<(u64, ThreadId)>, //ordered by vruntime, so the first one is next
+    vruntime: BTreeMap<ThreadId, u64>,
+    //never goes down. Threads that were away (new, or sleeping) start from here, so they don't get the CPU to
+    //themselves while they catch up on the time they weren't competing for it
+    min_vruntime: u64,
+}
+
+impl FairShare {
+    pub fn new() -> FairShare {
+        FairShare { ready: BTreeSet::new(), vruntime: BTreeMap::new(), min_vruntime: 0 }
+    }
+
+    //Virtual time for one tick: DEFAULT_PRIORITY threads advance by 1000, priority 0 by 9000, 15 by 562
+    fn tick_cost(priority: u8) -> u64 {
+        1000 * (DEFAULT_PRIORITY as u64 + 1) / (priority as u64 + 1)
+    }
+}
+
+impl Default for FairShare {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Policy for FairShare {
+    fn name(&self) -> &'static str {
+        "fair-share"
+    }
+
+    fn enqueue(&mut self, id: ThreadId, _priority: u8) {
+        let vruntime = self.vruntime.entry(id).or_insert(0);
+        *vruntime = (*vruntime).max(self.min_vruntime);
+        self.ready.insert((*vruntime, id));
+    }
+
+    fn next(&mut self) -> Option<ThreadId> {
+        let (vruntime, id) = self.ready.pop_first()?;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(id)
+    }
+
+    fn has_ready(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    fn tick(&mut self, id: ThreadId, priority: u8, ran: u32) -> bool {
+        let vruntime = self.vruntime.entry(id).or_insert(self.min_vruntime);
+        *vruntime += Self::tick_cost(priority);
+        let vruntime = *vruntime;
+        match self.ready.first() {
+            Some(&(waiting, _)) => ran >= MIN_GRANULARITY_TICKS && waiting < vruntime,
+            None => false,
+        }
+    }
+
+    fn exit(&mut self, id: ThreadId) {
+        self.vruntime.remove(&id);
+    }
+}
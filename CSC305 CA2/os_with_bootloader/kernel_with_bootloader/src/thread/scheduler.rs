@@ -0,0 +1,310 @@
+//The scheduler behind thread.rs.
+//Ready threads are handed to the Policy (policy.rs), which decides who runs next and when the running thread has
+//had enough. Threads that sleep, wait in join() or have finished aren't handed to it.
+//When nothing is ready, the idle thread halts the CPU until the next interrupt.
+//Each timer tick is charged to the thread it interrupted, which gives the statistics listed by thread::ps().
+//The scheduler is only ever locked with interrupts disabled: from the switch interrupt itself, or by thread
+//code through with_scheduler(). So the timer can never interrupt a thread that holds the lock.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::policy::{Policy, RoundRobin};
+use super::switch::{self, FpuState};
+use super::ThreadId;
+use crate::interrupts::exceptions::ExceptionContext;
+use crate::task::TaskId;
+use crate::time;
+
+/// Stack size of spawned threads.
+pub const STACK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    Ready,
+    Sleeping { until: u64 }, //tick
+    Blocked,                 //waiting in join() or parked (see thread::park)
+    Finished,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            State::Running => "running",
+            State::Ready => "ready",
+            State::Sleeping { .. } => "sleeping",
+            State::Blocked => "blocked",
+            State::Finished => "finished",
+        };
+        f.pad(name) //so width and alignment work in tables
+    }
+}
+
+/// What a thread has used so far, in timer ticks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub cpu_ticks: u64,  //ticks it was running when the timer fired
+    pub switches: u64,   //how often it was switched to
+    pub wait_ticks: u64, //time spent ready but waiting for the CPU
+    ready_since: u64,
+}
+
+pub(super) struct Thread {
+    pub(super) id: ThreadId,
+    pub(super) name: &'static str,
+    pub(super) priority: u8,
+    pub(super) state: State,
+    pub(super) stats: Stats,
+    context: u64, //where the switch stub saved the registers, on the thread's own stack
+    fpu: Box<FpuState>,
+    _stack: Option<Box<[u128]>>, //None for the boot thread, which runs on the bootloader's kernel stack
+    joiner: Option<ThreadId>,   //the thread waiting in join() for this one
+    detached: bool,             //nobody will join: free it as soon as it has finished
+    pub(super) task: Option<TaskId>, //the task it is polling, see task::current_id
+}
+
+pub(super) struct Scheduler {
+    pub(super) threads: BTreeMap<ThreadId, Thread>,
+    pub(super) policy: Box<dyn Policy>,
+    current: ThreadId,
+    idle: ThreadId,
+    ran: u32, //ticks since the current thread was switched to
+}
+
+//None until the first thread function is called
+static SCHEDULER: Mutex<Option<Scheduler>> = Mutex::new(None);
+
+/// Runs `f` with the scheduler locked and interrupts disabled. The first call turns the code calling it
+/// (the boot sequence) into the "main" thread and creates the idle thread.
+pub(super) fn with_scheduler<R>(f: impl FnOnce(&mut Scheduler) -> R) -> R {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let scheduler = scheduler.get_or_insert_with(|| Scheduler::new(Box::new(RoundRobin::new())));
+        f(scheduler)
+    })
+}
+
+/// Starts the scheduler with `policy`, the way with_scheduler() would with RoundRobin.
+/// Returns false (and leaves everything as it is) if threads are already in use.
+pub(super) fn init(policy: Box<dyn Policy>) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        if scheduler.is_some() {
+            return false;
+        }
+        *scheduler = Some(Scheduler::new(policy));
+        true
+    })
+}
+
+impl Scheduler {
+    fn new(policy: Box<dyn Policy>) -> Scheduler {
+        let main = Thread {
+            id: ThreadId::new(),
+            name: "main",
+            priority: super::policy::DEFAULT_PRIORITY,
+            state: State::Running,
+            stats: Stats { switches: 1, ..Stats::default() },
+            context: 0, //filled in at the first switch away from it
+            fpu: Box::new(FpuState::new()),
+            _stack: None,
+            joiner: None,
+            detached: true,
+            task: None,
+        };
+        let idle = Thread::new("idle", 0, idle_thread, 0);
+        let mut scheduler = Scheduler {
+            threads: BTreeMap::new(),
+            policy,
+            current: main.id,
+            idle: idle.id,
+            ran: 0,
+        };
+        scheduler.threads.insert(main.id, main);
+        scheduler.threads.insert(idle.id, idle);
+        scheduler
+    }
+
+    pub(super) fn current(&self) -> ThreadId {
+        self.current
+    }
+
+    pub(super) fn current_thread(&mut self) -> &mut Thread {
+        self.threads.get_mut(&self.current).expect("current thread missing")
+    }
+
+    pub(super) fn add(&mut self, thread: Thread) -> ThreadId {
+        let id = thread.id;
+        self.threads.insert(id, thread);
+        self.make_ready(id);
+        id
+    }
+
+    fn make_ready(&mut self, id: ThreadId) {
+        if let Some(thread) = self.threads.get_mut(&id) {
+            thread.state = State::Ready;
+            thread.stats.ready_since = time::ticks();
+            if id != self.idle {
+                self.policy.enqueue(id, thread.priority);
+            }
+        }
+    }
+
+    /// Puts the current thread to sleep (or to wait for a join) until something makes it ready again.
+    /// It keeps running until it enters the switch interrupt with [switch::yield_cpu].
+    pub(super) fn block_current(&mut self, state: State) {
+        self.current_thread().state = state;
+    }
+
+    /// Makes `id` ready again if it is blocked.
+    pub(super) fn unblock(&mut self, id: ThreadId) {
+        if self.threads.get(&id).is_some_and(|thread| thread.state == State::Blocked) {
+            self.make_ready(id);
+        }
+    }
+
+    /// Marks the current thread as finished and wakes whoever waits in join() for it.
+    pub(super) fn finish_current(&mut self) {
+        let thread = self.current_thread();
+        thread.state = State::Finished;
+        let joiner = thread.joiner.take();
+        self.policy.exit(self.current);
+        if let Some(joiner) = joiner {
+            self.make_ready(joiner);
+        }
+    }
+
+    /// Takes `id` out if it has finished, so its stack can be freed. Otherwise registers `joiner` to be
+    /// woken when it finishes.
+    pub(super) fn reap_or_wait(&mut self, id: ThreadId, joiner: ThreadId) -> Option<Thread> {
+        let thread = self.threads.get_mut(&id).expect("joining a thread that doesn't exist");
+        if thread.state == State::Finished {
+            return self.threads.remove(&id);
+        }
+        thread.joiner = Some(joiner);
+        None
+    }
+
+    /// Lets `id` be freed once it finishes (right away if it already has).
+    pub(super) fn detach(&mut self, id: ThreadId) -> Option<Thread> {
+        let thread = self.threads.get_mut(&id)?;
+        thread.detached = true;
+        if thread.state == State::Finished {
+            return self.threads.remove(&id);
+        }
+        None
+    }
+
+    /// Takes out detached threads that have finished (but not the current one: we're still on its stack).
+    pub(super) fn reap_detached(&mut self) -> Vec<Thread> {
+        let current = self.current;
+        let finished: Vec<ThreadId> = self
+            .threads
+            .values()
+            .filter(|thread| thread.detached && thread.state == State::Finished && thread.id != current)
+            .map(|thread| thread.id)
+            .collect();
+        finished.into_iter().filter_map(|id| self.threads.remove(&id)).collect()
+    }
+
+    fn wake_sleepers(&mut self, now: u64) {
+        let woken: Vec<ThreadId> = self
+            .threads
+            .values()
+            .filter(|thread| matches!(thread.state, State::Sleeping { until } if until <= now))
+            .map(|thread| thread.id)
+            .collect();
+        for id in woken {
+            self.make_ready(id);
+        }
+    }
+
+    //Charges the tick to the current thread. Returns true if it should make room for another one.
+    fn tick(&mut self) -> bool {
+        self.ran += 1;
+        let (current, idle, ran) = (self.current, self.idle, self.ran);
+        let thread = self.current_thread();
+        thread.stats.cpu_ticks += 1;
+        let priority = thread.priority;
+        current == idle || self.policy.tick(current, priority, ran)
+    }
+
+    //Saves the current thread's state and picks the next one. Returns the context to continue with.
+    fn switch(&mut self, context: *mut ExceptionContext) -> *mut ExceptionContext {
+        let thread = self.current_thread();
+        thread.context = context as u64;
+        thread.fpu.save();
+        if thread.state == State::Running {
+            self.make_ready(self.current);
+        }
+
+        let next = self.policy.next().unwrap_or(self.idle);
+        self.current = next;
+        self.ran = 0;
+        let thread = self.current_thread();
+        thread.state = State::Running;
+        thread.stats.switches += 1;
+        thread.stats.wait_ticks += time::ticks() - thread.stats.ready_since;
+        thread.fpu.restore();
+        thread.context as *mut ExceptionContext
+    }
+}
+
+impl Thread {
+    /// A thread that starts in `entry(arg)` on a new stack.
+    pub(super) fn new(name: &'static str, priority: u8, entry: extern "C" fn(usize) -> !, arg: usize) -> Thread {
+        let mut stack = alloc::vec![0u128; STACK_SIZE / 16].into_boxed_slice();
+        let context = switch::initial_context(&mut stack, entry, arg);
+        Thread {
+            id: ThreadId::new(),
+            name,
+            priority,
+            state: State::Ready,
+            //ready from now on; the idle thread never goes through make_ready before it first runs
+            stats: Stats { ready_since: time::ticks(), ..Stats::default() },
+            context,
+            fpu: Box::new(FpuState::new()),
+            _stack: Some(stack),
+            joiner: None,
+            detached: false,
+            task: None,
+        }
+    }
+}
+
+extern "C" fn idle_thread(_: usize) -> ! {
+    loop {
+        interrupts::enable_and_hlt();
+    }
+}
+
+/// Called by the timer interrupt after the tick has been counted.
+pub(super) fn on_timer(context: *mut ExceptionContext) -> *mut ExceptionContext {
+    //None: no threads yet. Locked: can't happen, since it is only locked with interrupts disabled
+    let Some(mut guard) = SCHEDULER.try_lock() else {
+        return context;
+    };
+    let Some(scheduler) = guard.as_mut() else {
+        return context;
+    };
+    scheduler.wake_sleepers(time::ticks());
+    //nobody else wants to run: carry on, however long the current thread has had the CPU
+    if !scheduler.tick() || !scheduler.policy.has_ready() {
+        return context;
+    }
+    scheduler.switch(context)
+}
+
+/// Called by the yield interrupt: the current thread gives up the CPU.
+pub(super) fn on_yield(context: *mut ExceptionContext) -> *mut ExceptionContext {
+    let mut guard = SCHEDULER.lock();
+    match guard.as_mut() {
+        Some(scheduler) => scheduler.switch(context),
+        None => context,
+    }
+}
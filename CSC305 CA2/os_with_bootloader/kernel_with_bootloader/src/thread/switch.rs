@@ -0,0 +1,155 @@
+/*The context switch. A thread's registers are saved on its own stack, in the same layout the exception stub
+uses (ExceptionContext): the CPU pushes the interrupt stack frame, we push a vector number, a dummy error code and
+all general purpose registers. Switching threads is then just returning a different stack pointer from the
+dispatcher: the stub loads it, pops the other thread's registers and `iretq`s into wherever that thread was.
+Two interrupts end up here: the timer (preemption) and a software interrupt that threads raise to give up the
+CPU themselves (yield_now, sleep, join, exit).
+Ref: https://wiki.osdev.org/Kernel_Multitasking and https://wiki.osdev.org/Context_Switching */
+
+use core::arch::{asm, naked_asm};
+use core::mem::size_of;
+
+use x86_64::instructions::segmentation::{Segment, CS, SS};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrameValue};
+use x86_64::VirtAddr;
+
+use super::scheduler;
+use crate::interrupts::exceptions::{ExceptionContext, Registers};
+use crate::interrupts::{self, InterruptIndex};
+
+/// The software interrupt threads use to enter the scheduler (`int 0x81`).
+pub const YIELD_VECTOR: u8 = 0x81;
+
+//interrupts enabled (bit 9) plus the always-set bit 1
+const INITIAL_RFLAGS: u64 = 0x202;
+
+//Same layout as the exception trampolines: a dummy error code and the vector, then the common stub
+#[unsafe(naked)]
+extern "C" fn timer_entry() {
+    naked_asm!("push 0", "push {vector}", "jmp {common}", vector = const InterruptIndex::Timer as u8, common = sym switch_stub);
+}
+
+#[unsafe(naked)]
+extern "C" fn yield_entry() {
+    naked_asm!("push 0", "push {vector}", "jmp {common}", vector = const YIELD_VECTOR, common = sym switch_stub);
+}
+
+//Saves the registers, asks the dispatcher which context to continue with and restores that one.
+//The stack is 16-byte aligned at the call for the same reason as in the exception stub.
+#[unsafe(naked)]
+extern "C" fn switch_stub() {
+    naked_asm!(
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov rdi, rsp", //&mut ExceptionContext of the interrupted thread
+        "cld",
+        "call {dispatch}",
+        "mov rsp, rax", //the context of the thread to continue, maybe on another stack
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        "add rsp, 16", //vector and error code
+        "iretq",
+        dispatch = sym switch_dispatch,
+    );
+}
+
+extern "C" fn switch_dispatch(context: *mut ExceptionContext) -> *mut ExceptionContext {
+    let vector = unsafe { (*context).vector };
+    if vector == InterruptIndex::Timer as u64 {
+        interrupts::timer_tick();
+        scheduler::on_timer(context)
+    } else {
+        scheduler::on_yield(context)
+    }
+}
+
+fn addr(entry: extern "C" fn()) -> VirtAddr {
+    VirtAddr::new(entry as usize as u64)
+}
+
+/// Points the timer and yield entries of `idt` at the switch stubs.
+pub fn set_handlers(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        idt[InterruptIndex::Timer as usize].set_handler_addr(addr(timer_entry));
+        idt[YIELD_VECTOR as usize].set_handler_addr(addr(yield_entry));
+    }
+}
+
+/// Enters the scheduler, which may run other threads before this one continues.
+pub(super) fn yield_cpu() {
+    unsafe { asm!("int {vector}", vector = const YIELD_VECTOR) };
+}
+
+/// Builds the saved context of a thread that hasn't run yet at the top of `stack`, so that switching to it
+/// "returns" into `entry(arg)` with interrupts enabled. Returns the context pointer to store for the thread.
+pub(super) fn initial_context(stack: &mut [u128], entry: extern "C" fn(usize) -> !, arg: usize) -> u64 {
+    let top = stack.as_mut_ptr_range().end as u64; //16-byte aligned, since the stack is made of u128s
+    //as if `entry` had been called: a return address on the stack. It is 0, so backtraces stop there
+    let entry_rsp = top - 8;
+    let context = entry_rsp - size_of::<ExceptionContext>() as u64;
+    unsafe {
+        (entry_rsp as *mut u64).write(0);
+        (context as *mut ExceptionContext).write(ExceptionContext {
+            registers: Registers { rdi: arg as u64, ..Registers::default() },
+            vector: YIELD_VECTOR as u64,
+            error_code: 0,
+            stack_frame: InterruptStackFrameValue {
+                instruction_pointer: VirtAddr::new(entry as usize as u64),
+                code_segment: CS::get_reg().0 as u64,
+                cpu_flags: INITIAL_RFLAGS,
+                stack_pointer: VirtAddr::new(entry_rsp),
+                stack_segment: SS::get_reg().0 as u64,
+            },
+        });
+    }
+    context
+}
+
+/// The x87/MMX/SSE registers, in the 512-byte layout FXSAVE writes. The general purpose registers are saved
+/// by the stub above; these are saved and restored by the scheduler on every switch.
+#[repr(C, align(16))]
+pub(super) struct FpuState([u8; 512]);
+
+impl FpuState {
+    /// The state after FNINIT with every SSE exception masked, which is what a new thread starts with.
+    pub(super) fn new() -> FpuState {
+        let mut area = [0u8; 512];
+        area[0..2].copy_from_slice(&0x037fu16.to_le_bytes()); //x87 control word: all exceptions masked
+        area[24..28].copy_from_slice(&0x1f80u32.to_le_bytes()); //MXCSR: all exceptions masked
+        FpuState(area)
+    }
+
+    pub(super) fn save(&mut self) {
+        unsafe { asm!("fxsave64 [{}]", in(reg) self.0.as_mut_ptr(), options(nostack, preserves_flags)) };
+    }
+
+    pub(super) fn restore(&self) {
+        unsafe { asm!("fxrstor64 [{}]", in(reg) self.0.as_ptr(), options(nostack, preserves_flags)) };
+    }
+}
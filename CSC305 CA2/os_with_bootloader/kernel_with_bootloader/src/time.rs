@@ -0,0 +1,105 @@
+/*Kernel time, based on the Programmable Interval Timer (PIT).
+The PIT counts down from a divisor at 1.193182 MHz and raises IRQ 0 (our timer interrupt) every time it reaches 0.
+The BIOS leaves the divisor at 65536, which is only about 18.2 interrupts per second. We program our own divisor
+and count the interrupts, which gives a monotonic clock: ticks() never goes backwards.
+Ref: https://wiki.osdev.org/Programmable_Interval_Timer */
+
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::time::Duration;
+
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+/// The frequency the PIT's oscillator runs at.
+pub const PIT_BASE_FREQUENCY: u32 = 1_193_182;
+
+/// The timer interrupt frequency interrupts::init() sets up: one tick per millisecond.
+pub const DEFAULT_FREQUENCY_HZ: u32 = 1000;
+
+//PIT I/O ports
+const CHANNEL_0_PORT: u16 = 0x40;
+const COMMAND_PORT: u16 = 0x43;
+//channel 0, send the divisor as low byte then high byte, mode 3 (square wave), binary counting
+const CHANNEL_0_SQUARE_WAVE: u8 = 0b0011_0110;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+//0 means the PIT hasn't been programmed yet; the BIOS default is 65536
+static DIVISOR: AtomicU32 = AtomicU32::new(0);
+
+/// Programs PIT channel 0 to interrupt `frequency_hz` times per second (as close as the divisor allows:
+/// between about 19 Hz and 1.19 MHz). Returns the frequency actually used.
+pub fn init(frequency_hz: u32) -> u32 {
+    let divisor = (PIT_BASE_FREQUENCY / frequency_hz.max(1)).clamp(1, 65535);
+
+    let mut command: Port<u8> = Port::new(COMMAND_PORT);
+    let mut channel_0: Port<u8> = Port::new(CHANNEL_0_PORT);
+    interrupts::without_interrupts(|| unsafe {
+        command.write(CHANNEL_0_SQUARE_WAVE);
+        channel_0.write((divisor & 0xff) as u8);
+        channel_0.write((divisor >> 8) as u8);
+    });
+    DIVISOR.store(divisor, Ordering::Relaxed);
+
+    PIT_BASE_FREQUENCY / divisor
+}
+
+/// Called by the timer interrupt handler.
+pub(crate) fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Timer interrupts since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Timer interrupts per second. 0 before init().
+pub fn frequency() -> u32 {
+    match DIVISOR.load(Ordering::Relaxed) {
+        0 => 0,
+        divisor => PIT_BASE_FREQUENCY / divisor,
+    }
+}
+
+/// Time since the PIT was programmed.
+pub fn uptime() -> Duration {
+    ticks_to_duration(ticks())
+}
+
+/// How long `ticks` timer interrupts take at the current frequency.
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    let divisor = DIVISOR.load(Ordering::Relaxed) as u128;
+    //each tick is divisor / PIT_BASE_FREQUENCY seconds; u128 so long uptimes don't overflow
+    let nanos = ticks as u128 * divisor * 1_000_000_000 / PIT_BASE_FREQUENCY as u128;
+    Duration::from_nanos(nanos as u64)
+}
+
+/// How many ticks make up `duration`, rounded up so waits are never shorter than asked for.
+/// u64::MAX (which is never reached) for durations too long to count in ticks, like Duration::MAX.
+/// Add it to a tick count with saturating_add.
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    let divisor = DIVISOR.load(Ordering::Relaxed).max(1) as u128;
+    let ticks = (duration.as_nanos() * PIT_BASE_FREQUENCY as u128).div_ceil(divisor * 1_000_000_000);
+    u64::try_from(ticks).unwrap_or(u64::MAX)
+}
+
+/// Halts the CPU until at least `ms` milliseconds have passed. Interrupts must be enabled,
+/// since only the timer interrupt wakes us up.
+pub fn sleep_ms(ms: u64) {
+    sleep(Duration::from_millis(ms));
+}
+
+pub fn sleep(duration: Duration) {
+    assert!(interrupts::are_enabled(), "sleep() needs interrupts enabled, otherwise it never wakes up");
+    let deadline = ticks().saturating_add(duration_to_ticks(duration));
+    loop {
+        //check and halt with interrupts off, then `sti; hlt` atomically, so a tick that arrives
+        //right after the check still wakes us instead of being missed
+        interrupts::disable();
+        if ticks() >= deadline {
+            interrupts::enable();
+            break;
+        }
+        interrupts::enable_and_hlt();
+    }
+}
@@ -89,6 +89,37 @@ impl FrameBufferWriter {
         self.y_pos = y_pos.unwrap_or(self.y_pos);
     }
 
+    ///where the next character goes
+    pub fn x_y_pos(&self) -> (usize, usize) {
+        (self.x_pos, self.y_pos)
+    }
+
+    ///where the character `count` characters after one at `pos` goes, wrapping lines like write_char does.
+    ///Only for printable characters (no newlines or backspaces in between)
+    pub fn pos_after(&self, pos: (usize, usize), count: usize) -> (usize, usize) {
+        let line_height = font_constants::CHAR_RASTER_HEIGHT.val() + LINE_SPACING;
+        let wrap = |(x, y): (usize, usize)| match x + font_constants::CHAR_RASTER_WIDTH >= self.width() {
+            true => (BORDER_PADDING, y + line_height),
+            false => (x, y),
+        };
+        (0..count).fold(wrap(pos), |(x, y), _| wrap((x + font_constants::CHAR_RASTER_WIDTH + LETTER_SPACING, y)))
+    }
+
+    ///whether a character at `pos` is still on the screen. write_char clears the screen for one that isn't
+    pub fn fits(&self, pos: (usize, usize)) -> bool {
+        pos.1 + font_constants::CHAR_RASTER_HEIGHT.val() + BORDER_PADDING < self.height()
+    }
+
+    ///Draws (or erases) a cursor under the character at the current position, without moving.
+    ///It goes in the spacing between lines, so it doesn't touch the character itself
+    pub fn draw_cursor(&mut self, visible: bool) {
+        let y = self.y_pos + font_constants::CHAR_RASTER_HEIGHT.val();
+        let intensity = if visible { 255 } else { 0 };
+        for x in 0..font_constants::CHAR_RASTER_WIDTH {
+            self.write_pixel(self.x_pos + x, y, intensity);
+        }
+    }
+
     fn newline(&mut self) {
         self.y_pos += font_constants::CHAR_RASTER_HEIGHT.val() + LINE_SPACING;
         self.carriage_return()